@@ -0,0 +1,126 @@
+//! Concurrent `Get`/`Add` throughput of the shared todo store, compared with the
+//! `tokio::sync::Mutex` every RPC used to serialize on.
+//!
+//! Run with `cargo bench --bench storage`.
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use grpc_todos::{
+    auth::User,
+    store::{SharedStore, TodoStore},
+    Todo, TodoDescriptor, TodoIdentifier,
+};
+use tokio::{runtime::Runtime, sync::Mutex};
+
+const TASKS: usize = 8;
+const OPS_PER_TASK: usize = 1000;
+const PREFILLED: u32 = 1000;
+
+#[derive(Clone)]
+enum Storage {
+    /// The previous design: one async mutex around the whole store.
+    Mutex(Arc<Mutex<TodoStore>>),
+    Shared(SharedStore),
+}
+
+impl Storage {
+    fn mutex() -> Self {
+        Storage::Mutex(Arc::new(Mutex::new(prefilled())))
+    }
+
+    fn shared() -> Self {
+        let store = SharedStore::default();
+        *store.write() = prefilled();
+        Storage::Shared(store)
+    }
+
+    async fn get(&self, id: u32) -> Option<Todo> {
+        match self {
+            Storage::Mutex(store) => store.lock().await.get(&user(), id).ok().cloned(),
+            Storage::Shared(store) => store.read().get(&user(), id).ok().cloned(),
+        }
+    }
+
+    async fn add(&self, id: u32) {
+        let result = match self {
+            Storage::Mutex(store) => store.lock().await.insert(&user(), id, todo(id)),
+            Storage::Shared(store) => store.write().insert(&user(), id, todo(id)),
+        };
+        result.unwrap();
+    }
+}
+
+fn prefilled() -> TodoStore {
+    let mut store = TodoStore::default();
+    for id in 0..PREFILLED {
+        store.insert(&user(), id, todo(id)).unwrap();
+    }
+    store
+}
+
+fn user() -> User {
+    User::anonymous()
+}
+
+fn todo(id: u32) -> Todo {
+    Todo {
+        id: Some(TodoIdentifier { id }),
+        descriptor: Some(TodoDescriptor {
+            title: format!("todo {}", id),
+            description: None,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Runs `TASKS` tasks in parallel, each doing `OPS_PER_TASK` operations of which one in
+/// `add_every` is an `Add` and the rest are `Get`s.
+async fn run(storage: Storage, next_id: Arc<AtomicU32>, add_every: Option<usize>) {
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|task| {
+            let storage = storage.clone();
+            let next_id = next_id.clone();
+            tokio::spawn(async move {
+                for op in 0..OPS_PER_TASK {
+                    if add_every.is_some_and(|every| op % every == 0) {
+                        storage.add(next_id.fetch_add(1, Ordering::Relaxed)).await;
+                    } else {
+                        let id = ((task * OPS_PER_TASK + op) as u32) % PREFILLED;
+                        assert!(storage.get(id).await.is_some());
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
+
+fn bench_storage(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    for (workload, add_every) in [("get", None), ("get_add_90_10", Some(10))] {
+        let mut group = c.benchmark_group(workload);
+        group.throughput(Throughput::Elements((TASKS * OPS_PER_TASK) as u64));
+
+        for (name, storage) in [("mutex", Storage::mutex()), ("rwlock", Storage::shared())] {
+            let next_id = Arc::new(AtomicU32::new(PREFILLED));
+
+            group.bench_function(BenchmarkId::from_parameter(name), |b| {
+                b.to_async(&runtime)
+                    .iter(|| run(storage.clone(), next_id.clone(), add_every));
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_storage);
+criterion_main!(benches);
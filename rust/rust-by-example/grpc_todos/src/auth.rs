@@ -0,0 +1,155 @@
+//! Bearer token authentication.
+//!
+//! [`Authenticator`] is a tonic interceptor that resolves the `authorization: Bearer <token>`
+//! metadata of every request to a [`User`] and stores it in the request extensions, where
+//! the service picks it up to select the caller's todos.
+
+use std::{collections::HashMap, sync::Arc};
+
+use tonic::{service::Interceptor, Request, Status};
+
+/// The user a request was authenticated as.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct User(pub String);
+
+impl User {
+    /// The user of requests that did not pass through an [`Authenticator`].
+    pub fn anonymous() -> Self {
+        Self(String::new())
+    }
+
+    /// Returns the user attached to `request`, or the anonymous user.
+    pub fn from_request<T>(request: &Request<T>) -> Self {
+        request
+            .extensions()
+            .get::<User>()
+            .cloned()
+            .unwrap_or_else(User::anonymous)
+    }
+}
+
+/// Maps bearer tokens to users.
+///
+/// A disabled authenticator lets every request through as the anonymous user.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    tokens: Option<Arc<HashMap<String, User>>>,
+}
+
+impl Authenticator {
+    pub fn new<T, U>(tokens: impl IntoIterator<Item = (T, U)>) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        let tokens = tokens
+            .into_iter()
+            .map(|(token, user)| (token.into(), User(user.into())))
+            .collect();
+
+        Self {
+            tokens: Some(Arc::new(tokens)),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma separated list of `token=user` pairs.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+
+        for pair in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            match pair.split_once('=') {
+                Some((token, user)) if !token.is_empty() && !user.is_empty() => {
+                    tokens.push((token, user));
+                }
+                _ => return Err(format!("expected token=user, got {:?}", pair)),
+            }
+        }
+
+        Ok(Self::new(tokens))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tokens.is_some()
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let tokens = match &self.tokens {
+            Some(tokens) => tokens,
+            None => return Ok(request),
+        };
+
+        let header = match request.metadata().get("authorization") {
+            Some(header) => header,
+            None => return Err(Status::unauthenticated("missing bearer token")),
+        };
+
+        let token = match header.to_str().ok().and_then(|v| v.strip_prefix("Bearer ")) {
+            Some(token) => token,
+            None => return Err(Status::unauthenticated("malformed authorization header")),
+        };
+
+        let user = match tokens.get(token) {
+            Some(user) => user.clone(),
+            None => return Err(Status::unauthenticated("invalid bearer token")),
+        };
+
+        request.extensions_mut().insert(user);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(value) = authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", value.parse().unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn valid_token_attaches_user() {
+        let mut auth = Authenticator::parse("secret=alice, other=bob").unwrap();
+
+        let request = auth.call(request(Some("Bearer other"))).unwrap();
+        assert_eq!(User::from_request(&request), User("bob".into()));
+    }
+
+    #[test]
+    fn missing_malformed_or_unknown_tokens_are_rejected() {
+        let mut auth = Authenticator::new([("secret", "alice")]);
+
+        for authorization in [None, Some("secret"), Some("Bearer nope")] {
+            let status = auth.call(request(authorization)).unwrap_err();
+            assert_eq!(status.code(), Code::Unauthenticated);
+        }
+    }
+
+    #[test]
+    fn disabled_authenticator_lets_anonymous_requests_through() {
+        let request = Authenticator::disabled().call(request(None)).unwrap();
+        assert_eq!(User::from_request(&request), User::anonymous());
+    }
+
+    #[test]
+    fn parse_rejects_pairs_without_user() {
+        assert!(Authenticator::parse("secret").is_err());
+        assert!(Authenticator::parse("secret=").is_err());
+    }
+}
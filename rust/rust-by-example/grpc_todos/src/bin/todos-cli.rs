@@ -0,0 +1,422 @@
+use std::{fs, path::PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use grpc_todos::{
+    todos_client::TodosClient, Todo, TodoChangeResponse, TodoDescriptor, TodoIdentifier,
+    TodoListRequest, TodoPriority, TodoStatus, TodoUpdateRequest,
+};
+use prost_types::Timestamp;
+use serde_json::{json, Value};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Certificate, Channel, ClientTlsConfig, Identity},
+};
+use tonic_types::StatusExt;
+
+/// Command line client for the todos gRPC service.
+#[derive(Parser)]
+#[command(name = "todos-cli", version)]
+struct Cli {
+    /// Address of the todos server
+    #[arg(long, env = "TODOS_ADDR", default_value = "http://127.0.0.1:8000")]
+    addr: String,
+
+    /// Bearer token sent with every request
+    #[arg(long, env = "TODOS_TOKEN")]
+    token: Option<String>,
+
+    /// PEM CA certificate to verify an `https://` server with, instead of the system roots
+    #[arg(long, env = "TODOS_CA_CERT")]
+    ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate for servers requiring mutual TLS, requires `--key`
+    #[arg(long, env = "TODOS_CLIENT_CERT", requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// PEM private key of `--cert`
+    #[arg(long, env = "TODOS_CLIENT_KEY", requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Output format
+    #[arg(long, short, value_enum, default_value_t = Output::Table)]
+    output: Output,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Add a new todo
+    Add {
+        id: u32,
+        #[arg(long)]
+        title: String,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long, value_enum, default_value_t = Status::New)]
+        status: Status,
+        /// Due date as an RFC 3339 timestamp, e.g. 2024-01-31T18:00:00Z
+        #[arg(long, value_parser = parse_timestamp)]
+        due: Option<Timestamp>,
+        #[arg(long, value_enum, default_value_t = Priority::None)]
+        priority: Priority,
+        /// Tag to attach, may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Id of the todo this one is a subtask of
+        #[arg(long)]
+        parent: Option<u32>,
+    },
+    /// Show a single todo
+    Get { id: u32 },
+    /// List todos
+    List {
+        /// Only todos carrying this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only todos past their due date that are not completed
+        #[arg(long)]
+        overdue: bool,
+    },
+    /// Update the given fields of a todo
+    Update {
+        id: u32,
+        #[arg(long, value_enum)]
+        status: Option<Status>,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        /// Due date as an RFC 3339 timestamp
+        #[arg(long, value_parser = parse_timestamp)]
+        due: Option<Timestamp>,
+        #[arg(long, value_enum)]
+        priority: Option<Priority>,
+        /// Replaces the tags, may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        #[arg(long)]
+        parent: Option<u32>,
+    },
+    /// Remove a todo
+    Remove { id: u32 },
+    /// Print a todo every time it changes
+    Watch { id: u32 },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Status {
+    New,
+    Ongoing,
+    Completed,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Priority {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl From<Priority> for TodoPriority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::None => TodoPriority::None,
+            Priority::Low => TodoPriority::Low,
+            Priority::Medium => TodoPriority::Medium,
+            Priority::High => TodoPriority::High,
+        }
+    }
+}
+
+fn parse_timestamp(value: &str) -> Result<Timestamp, String> {
+    value
+        .parse()
+        .map_err(|_| format!("not an RFC 3339 timestamp: {}", value))
+}
+
+impl From<Status> for TodoStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::New => TodoStatus::New,
+            Status::Ongoing => TodoStatus::Ongoing,
+            Status::Completed => TodoStatus::Completed,
+        }
+    }
+}
+
+type Client = TodosClient<InterceptedService<Channel, BearerToken>>;
+
+/// Adds the `authorization` header to every request.
+struct BearerToken(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for BearerToken {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(value) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let token = match cli.token {
+        Some(token) => Some(format!("Bearer {}", token).parse()?),
+        None => None,
+    };
+    let mut endpoint = Channel::from_shared(cli.addr)?;
+    if cli.ca_cert.is_some() || cli.cert.is_some() {
+        let mut tls = ClientTlsConfig::new();
+        if let Some(ca_cert) = &cli.ca_cert {
+            tls = tls.ca_certificate(Certificate::from_pem(fs::read(ca_cert)?));
+        }
+        if let (Some(cert), Some(key)) = (&cli.cert, &cli.key) {
+            tls = tls.identity(Identity::from_pem(fs::read(cert)?, fs::read(key)?));
+        }
+        endpoint = endpoint.tls_config(tls)?;
+    }
+    let channel = endpoint.connect().await?;
+    let mut client = TodosClient::with_interceptor(channel, BearerToken(token));
+
+    if let Err(status) = run(&mut client, cli.command, cli.output).await {
+        eprintln!("error: {}", status.message());
+        if let Some(bad_request) = status.get_details_bad_request() {
+            for violation in bad_request.field_violations {
+                eprintln!("  {}: {}", violation.field, violation.description);
+            }
+        }
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn run(client: &mut Client, command: Command, output: Output) -> Result<(), tonic::Status> {
+    match command {
+        Command::Add {
+            id,
+            title,
+            description,
+            status,
+            due,
+            priority,
+            tags,
+            parent,
+        } => {
+            let todo = Todo {
+                id: Some(TodoIdentifier { id }),
+                status: TodoStatus::from(status).into(),
+                descriptor: Some(TodoDescriptor { title, description }),
+                due_at: due,
+                priority: TodoPriority::from(priority).into(),
+                tags,
+                parent_id: parent.map(|id| TodoIdentifier { id }),
+                revision: 0,
+            };
+            let response = client.add(todo).await?.into_inner();
+            print_change(&response, output);
+        }
+        Command::Get { id } => {
+            let todo = client.get(TodoIdentifier { id }).await?.into_inner();
+            print_todos(&[todo], output);
+        }
+        Command::List { tag, overdue } => {
+            let todos = client
+                .list(TodoListRequest { tag, overdue })
+                .await?
+                .into_inner()
+                .todos;
+            print_todos(&todos, output);
+        }
+        Command::Update {
+            id,
+            status,
+            title,
+            description,
+            due,
+            priority,
+            tags,
+            parent,
+        } => {
+            let mut paths = Vec::new();
+            let mut todo = Todo {
+                id: Some(TodoIdentifier { id }),
+                ..Default::default()
+            };
+            if let Some(status) = status {
+                todo.status = TodoStatus::from(status).into();
+                paths.push("status".to_string());
+            }
+            if let Some(title) = title {
+                todo.descriptor.get_or_insert_with(Default::default).title = title;
+                paths.push("descriptor.title".to_string());
+            }
+            if description.is_some() {
+                todo.descriptor
+                    .get_or_insert_with(Default::default)
+                    .description = description;
+                paths.push("descriptor.description".to_string());
+            }
+            if due.is_some() {
+                todo.due_at = due;
+                paths.push("due_at".to_string());
+            }
+            if let Some(priority) = priority {
+                todo.priority = TodoPriority::from(priority).into();
+                paths.push("priority".to_string());
+            }
+            if !tags.is_empty() {
+                todo.tags = tags;
+                paths.push("tags".to_string());
+            }
+            if let Some(parent) = parent {
+                todo.parent_id = Some(TodoIdentifier { id: parent });
+                paths.push("parent_id".to_string());
+            }
+            if paths.is_empty() {
+                return Err(tonic::Status::invalid_argument(
+                    "nothing to update, pass at least one field to change",
+                ));
+            }
+
+            let request = TodoUpdateRequest {
+                todo: Some(todo),
+                update_mask: Some(prost_types::FieldMask { paths }),
+            };
+            let response = client.update_todo(request).await?.into_inner();
+            print_change(&response, output);
+        }
+        Command::Remove { id } => {
+            let response = client.remove(TodoIdentifier { id }).await?.into_inner();
+            print_change(&response, output);
+        }
+        Command::Watch { id } => {
+            let mut stream = client.watch(TodoIdentifier { id }).await?.into_inner();
+            while let Some(todo) = stream.message().await? {
+                print_todos(&[todo], output);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_change(response: &TodoChangeResponse, output: Output) {
+    let id = response.id.as_ref().map(|id| id.id);
+    match output {
+        Output::Table => match id {
+            Some(id) => println!("{} (id {})", response.message, id),
+            None => println!("{}", response.message),
+        },
+        Output::Json => println!("{}", json!({ "id": id, "message": response.message })),
+    }
+}
+
+fn print_todos(todos: &[Todo], output: Output) {
+    match output {
+        Output::Table => print_table(todos),
+        Output::Json => {
+            for todo in todos {
+                println!("{}", todo_to_json(todo));
+            }
+        }
+    }
+}
+
+fn todo_to_json(todo: &Todo) -> Value {
+    let descriptor = todo.descriptor.clone().unwrap_or_default();
+    json!({
+        "id": todo.id.as_ref().map(|id| id.id),
+        "status": status_name(todo.status),
+        "title": descriptor.title,
+        "description": descriptor.description,
+        "due_at": todo.due_at.as_ref().map(ToString::to_string),
+        "priority": priority_name(todo.priority),
+        "tags": todo.tags,
+        "parent_id": todo.parent_id.as_ref().map(|id| id.id),
+        "revision": todo.revision,
+    })
+}
+
+fn print_table(todos: &[Todo]) {
+    let header = [
+        "ID",
+        "STATUS",
+        "PRIORITY",
+        "DUE",
+        "PARENT",
+        "TAGS",
+        "TITLE",
+        "DESCRIPTION",
+    ]
+    .map(String::from);
+    let rows: Vec<[String; 8]> = todos
+        .iter()
+        .map(|todo| {
+            let descriptor = todo.descriptor.clone().unwrap_or_default();
+            [
+                todo.id
+                    .as_ref()
+                    .map(|id| id.id.to_string())
+                    .unwrap_or_default(),
+                status_name(todo.status).to_string(),
+                priority_name(todo.priority).to_string(),
+                todo.due_at
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+                todo.parent_id
+                    .as_ref()
+                    .map(|id| id.id.to_string())
+                    .unwrap_or_default(),
+                todo.tags.join(","),
+                descriptor.title,
+                descriptor.description.unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn status_name(status: i32) -> &'static str {
+    TodoStatus::try_from(status)
+        .map(|status| status.as_str_name())
+        .unwrap_or("UNKNOWN")
+}
+
+fn priority_name(priority: i32) -> &'static str {
+    TodoPriority::try_from(priority)
+        .map(|priority| priority.as_str_name())
+        .unwrap_or("UNKNOWN")
+}
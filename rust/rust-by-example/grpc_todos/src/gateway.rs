@@ -0,0 +1,392 @@
+//! REST/JSON gateway onto the `Todos` service.
+//!
+//! | Method   | Path          | RPC          |
+//! |----------|---------------|--------------|
+//! | `GET`    | `/todos`      | `List`       |
+//! | `POST`   | `/todos`      | `Add`        |
+//! | `GET`    | `/todos/:id`  | `Get`        |
+//! | `PATCH`  | `/todos/:id`  | `UpdateTodo` |
+//! | `DELETE` | `/todos/:id`  | `Remove`     |
+//!
+//! `GET /todos` accepts the `tag` and `overdue=true` query parameters of `List`.
+//!
+//! Requests are authenticated by the same [`Authenticator`] as the gRPC service, using the
+//! `Authorization` header, and errors are mapped from their gRPC code to an HTTP status.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use prost_types::Timestamp;
+use serde::{Deserialize, Deserializer, Serialize};
+use tonic::{service::Interceptor, Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+use tower_http::cors::CorsLayer;
+
+use crate::{
+    auth::Authenticator, server::TodoService, todos_server::Todos, Todo, TodoChangeResponse,
+    TodoDescriptor, TodoIdentifier, TodoListRequest, TodoPriority, TodoStatus, TodoUpdateRequest,
+};
+
+#[derive(Clone)]
+struct Gateway {
+    service: TodoService,
+    auth: Authenticator,
+}
+
+/// Builds the gateway routes, serving `service` to the callers authenticated by `auth`.
+///
+/// The routes are open to cross-origin requests so browser frontends can call them directly.
+pub fn router(service: TodoService, auth: Authenticator) -> Router {
+    Router::new()
+        .route("/todos", get(list).post(add))
+        .route("/todos/:id", get(find).patch(update).delete(remove))
+        .with_state(Gateway { service, auth })
+        .layer(CorsLayer::permissive())
+}
+
+/// JSON representation of a [`Todo`]; `due_at` is an RFC 3339 timestamp.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TodoJson {
+    pub id: u32,
+    #[serde(default = "default_status")]
+    pub status: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub due_at: Option<String>,
+    #[serde(default = "default_priority")]
+    pub priority: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub parent_id: Option<u32>,
+    /// Set by the server, ignored in requests.
+    #[serde(default)]
+    pub revision: u64,
+}
+
+/// Body of `PATCH /todos/:id`; only the given fields are updated, and the optional ones are
+/// cleared by giving them as `null`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TodoPatchJson {
+    pub status: Option<String>,
+    pub title: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub due_at: Option<Option<String>>,
+    pub priority: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parent_id: Option<Option<u32>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ListQuery {
+    tag: Option<String>,
+    #[serde(default)]
+    overdue: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChangeJson {
+    pub id: Option<u32>,
+    pub message: String,
+}
+
+/// Tells a field given as `null`, `Some(None)`, from a missing one, `None` by default.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+fn default_status() -> String {
+    TodoStatus::New.as_str_name().into()
+}
+
+fn default_priority() -> String {
+    TodoPriority::None.as_str_name().into()
+}
+
+impl From<Todo> for TodoJson {
+    fn from(todo: Todo) -> Self {
+        let descriptor = todo.descriptor.unwrap_or_default();
+        Self {
+            id: todo.id.map(|id| id.id).unwrap_or_default(),
+            status: TodoStatus::try_from(todo.status)
+                .map(|status| status.as_str_name())
+                .unwrap_or("UNKNOWN")
+                .into(),
+            title: descriptor.title,
+            description: descriptor.description,
+            due_at: todo.due_at.map(|due_at| due_at.to_string()),
+            priority: TodoPriority::try_from(todo.priority)
+                .map(|priority| priority.as_str_name())
+                .unwrap_or("UNKNOWN")
+                .into(),
+            tags: todo.tags,
+            parent_id: todo.parent_id.map(|id| id.id),
+            revision: todo.revision,
+        }
+    }
+}
+
+impl From<TodoChangeResponse> for ChangeJson {
+    fn from(response: TodoChangeResponse) -> Self {
+        Self {
+            id: response.id.map(|id| id.id),
+            message: response.message,
+        }
+    }
+}
+
+impl Gateway {
+    /// Runs the interceptor on the `Authorization` header and attaches `message` to the result.
+    #[allow(clippy::result_large_err)]
+    fn request<T>(&self, headers: &HeaderMap, message: T) -> Result<tonic::Request<T>, Status> {
+        let mut request = tonic::Request::new(());
+        if let Some(value) = headers.get(AUTHORIZATION) {
+            let value = value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| Status::unauthenticated("malformed authorization header"))?;
+            request.metadata_mut().insert("authorization", value);
+        }
+
+        let (metadata, extensions, ()) = self.auth.clone().call(request)?.into_parts();
+        Ok(tonic::Request::from_parts(metadata, extensions, message))
+    }
+}
+
+async fn list(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<TodoJson>>, ApiError> {
+    let list = TodoListRequest {
+        tag: query.tag,
+        overdue: query.overdue,
+    };
+    let request = gateway.request(&headers, list)?;
+    let todos = gateway.service.list(request).await?.into_inner().todos;
+    Ok(Json(todos.into_iter().map(TodoJson::from).collect()))
+}
+
+async fn add(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Json(body): Json<TodoJson>,
+) -> Result<(StatusCode, Json<ChangeJson>), ApiError> {
+    let todo = Todo {
+        id: Some(TodoIdentifier { id: body.id }),
+        status: parse_status(&body.status)?,
+        descriptor: Some(TodoDescriptor {
+            title: body.title,
+            description: body.description,
+        }),
+        due_at: body.due_at.as_deref().map(parse_due_at).transpose()?,
+        priority: parse_priority(&body.priority)?,
+        tags: body.tags,
+        parent_id: body.parent_id.map(|id| TodoIdentifier { id }),
+        revision: 0,
+    };
+
+    let request = gateway.request(&headers, todo)?;
+    let response = gateway.service.add(request).await?.into_inner();
+    Ok((StatusCode::CREATED, Json(response.into())))
+}
+
+async fn find(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+) -> Result<Json<TodoJson>, ApiError> {
+    let request = gateway.request(&headers, TodoIdentifier { id })?;
+    let todo = gateway.service.get(request).await?.into_inner();
+    Ok(Json(todo.into()))
+}
+
+async fn update(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+    Json(body): Json<TodoPatchJson>,
+) -> Result<Json<ChangeJson>, ApiError> {
+    let mut paths = Vec::new();
+    let mut todo = Todo {
+        id: Some(TodoIdentifier { id }),
+        ..Default::default()
+    };
+    if let Some(status) = body.status {
+        todo.status = parse_status(&status)?;
+        paths.push("status".to_string());
+    }
+    if let Some(title) = body.title {
+        todo.descriptor.get_or_insert_with(Default::default).title = title;
+        paths.push("descriptor.title".to_string());
+    }
+    if let Some(description) = body.description {
+        todo.descriptor
+            .get_or_insert_with(Default::default)
+            .description = description;
+        paths.push("descriptor.description".to_string());
+    }
+    if let Some(due_at) = body.due_at {
+        todo.due_at = due_at.as_deref().map(parse_due_at).transpose()?;
+        paths.push("due_at".to_string());
+    }
+    if let Some(priority) = body.priority {
+        todo.priority = parse_priority(&priority)?;
+        paths.push("priority".to_string());
+    }
+    if let Some(tags) = body.tags {
+        todo.tags = tags;
+        paths.push("tags".to_string());
+    }
+    if let Some(parent_id) = body.parent_id {
+        todo.parent_id = parent_id.map(|id| TodoIdentifier { id });
+        paths.push("parent_id".to_string());
+    }
+    if paths.is_empty() {
+        return Err(Status::invalid_argument("nothing to update").into());
+    }
+
+    let update = TodoUpdateRequest {
+        todo: Some(todo),
+        update_mask: Some(prost_types::FieldMask { paths }),
+    };
+    let request = gateway.request(&headers, update)?;
+    let response = gateway.service.update_todo(request).await?.into_inner();
+    Ok(Json(response.into()))
+}
+
+async fn remove(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+) -> Result<Json<ChangeJson>, ApiError> {
+    let request = gateway.request(&headers, TodoIdentifier { id })?;
+    let response = gateway.service.remove(request).await?.into_inner();
+    Ok(Json(response.into()))
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_status(status: &str) -> Result<i32, Status> {
+    match TodoStatus::from_str_name(status) {
+        Some(status) => Ok(status.into()),
+        None => Err(invalid_field(
+            "status",
+            format!("unknown status: {}", status),
+        )),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_priority(priority: &str) -> Result<i32, Status> {
+    match TodoPriority::from_str_name(priority) {
+        Some(priority) => Ok(priority.into()),
+        None => Err(invalid_field(
+            "priority",
+            format!("unknown priority: {}", priority),
+        )),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_due_at(due_at: &str) -> Result<Timestamp, Status> {
+    due_at
+        .parse()
+        .map_err(|_| invalid_field("due_at", format!("not an RFC 3339 timestamp: {}", due_at)))
+}
+
+fn invalid_field(field: &str, description: String) -> Status {
+    Status::with_error_details(
+        Code::InvalidArgument,
+        "request has invalid fields",
+        ErrorDetails::with_bad_request_violation(field, description),
+    )
+}
+
+/// A gRPC status rendered as an HTTP error response.
+pub struct ApiError(Status);
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        Self(status)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorJson {
+    code: String,
+    message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    field_violations: Vec<FieldViolationJson>,
+}
+
+#[derive(Serialize)]
+struct FieldViolationJson {
+    field: String,
+    description: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.0;
+
+        let http_status = match status.code() {
+            Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+                StatusCode::BAD_REQUEST
+            }
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+            Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let field_violations = status
+            .get_details_bad_request()
+            .map(|bad_request| bad_request.field_violations)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|violation| FieldViolationJson {
+                field: violation.field,
+                description: violation.description,
+            })
+            .collect();
+
+        let body = ErrorJson {
+            code: format!("{:?}", status.code()),
+            message: status.message().into(),
+            field_violations,
+        };
+
+        (http_status, Json(body)).into_response()
+    }
+}
@@ -0,0 +1,16 @@
+pub mod auth;
+pub mod gateway;
+pub mod server;
+pub mod shutdown;
+pub mod store;
+pub mod telemetry;
+pub mod tls;
+// generated by build.rs from proto/todos.proto; left as tonic-build writes it
+#[rustfmt::skip]
+pub mod todos;
+pub mod validation;
+
+pub use todos::*;
+
+/// Encoded file descriptor set of `todos.proto`, served by the reflection service.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("todos_descriptor");
@@ -1,34 +1,219 @@
-pub mod server;
-pub mod todos;
-use server::TodoService;
-use todos::todos_server::TodosServer;
-pub use todos::*;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use futures::FutureExt;
+use grpc_todos::{
+    auth::Authenticator,
+    gateway,
+    server::TodoService,
+    shutdown,
+    telemetry::{metrics_router, Metrics},
+    tls::{self, TlsFiles},
+    todos_server::TodosServer,
+    FILE_DESCRIPTOR_SET,
+};
+use hyper::server::accept;
+use tokio::{net::TcpListener, sync::watch};
 use tonic::transport::Server;
+use tower::Layer;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+/// gRPC and REST server for the todos service.
+#[derive(Parser)]
+#[command(name = "grpc_todos", version)]
+struct Config {
+    /// Address to serve gRPC and gRPC-Web on
+    #[arg(long, env = "TODOS_BIND_ADDR", default_value = "127.0.0.1:8000")]
+    addr: SocketAddr,
+
+    /// Address to serve the REST/JSON gateway on
+    #[arg(long, env = "TODOS_HTTP_ADDR", default_value = "127.0.0.1:8080")]
+    http_addr: SocketAddr,
+
+    /// Address to serve Prometheus metrics on, at `/metrics`
+    #[arg(long, env = "TODOS_METRICS_ADDR", default_value = "127.0.0.1:9090")]
+    metrics_addr: SocketAddr,
+
+    /// Comma separated `token=user` pairs accepted as bearer tokens.
+    /// Without tokens every caller shares the same anonymous todo list.
+    #[arg(long, env = "TODOS_TOKENS", value_parser = Authenticator::parse)]
+    tokens: Option<Authenticator>,
+
+    /// PEM certificate to serve gRPC and the REST gateway over TLS with, requires `--tls-key`
+    #[arg(long, env = "TODOS_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of `--tls-cert`
+    #[arg(long, env = "TODOS_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
 
-mod todos_proto {
-    include!("todos.rs");
+    /// PEM CA certificate that clients must present a certificate signed by (mutual TLS)
+    #[arg(long, env = "TODOS_TLS_CLIENT_CA", requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
 
-    pub(crate) const FILE_DESCRIPTOR: &[u8] =
-        tonic::include_file_descriptor_set!("todos_descriptor");
+    /// Seconds a client gets to complete the TLS handshake before it is disconnected
+    #[arg(long, env = "TODOS_TLS_HANDSHAKE_TIMEOUT", default_value_t = tls::DEFAULT_HANDSHAKE_TIMEOUT.as_secs())]
+    tls_handshake_timeout: u64,
+
+    /// Seconds to keep serving after reporting NOT_SERVING on shutdown, so load balancers
+    /// polling the health service stop routing calls here first
+    #[arg(long, env = "TODOS_SHUTDOWN_GRACE", default_value_t = shutdown::DEFAULT_GRACE.as_secs())]
+    shutdown_grace: u64,
+}
+
+impl Config {
+    fn tls(&self) -> Option<TlsFiles> {
+        Some(TlsFiles {
+            cert: self.tls_cert.clone()?,
+            key: self.tls_key.clone()?,
+            client_ca: self.tls_client_ca.clone(),
+        })
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `RUST_LOG` overrides the default of logging every RPC
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let config = Config::parse();
+    let tls = config.tls();
+
     let reflection_service = tonic_reflection::server::Builder::configure()
-        .register_encoded_file_descriptor_set(todos_proto::FILE_DESCRIPTOR)
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
         .build()
         .unwrap();
 
-    let addr = "127.0.0.1:8000".parse()?;
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    shutdown::report_serving(&mut health_reporter).await;
+
     let inner = TodoService::default();
 
-    println!("Server listening on {}", addr);
+    let auth = config.tokens.unwrap_or_else(Authenticator::disabled);
+    if !auth.is_enabled() {
+        info!("No tokens configured, authentication is disabled");
+    }
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let stopped = move || {
+        let mut stop_rx = stop_rx.clone();
+        async move {
+            let _ = stop_rx.wait_for(|stop| *stop).await;
+        }
+    };
 
-    Server::builder()
-        .add_service(TodosServer::new(inner))
-        .add_service(reflection_service)
-        .serve(addr)
-        .await?;
+    tokio::spawn({
+        let inner = inner.clone();
+        let grace = Duration::from_secs(config.shutdown_grace);
+        async move {
+            shutdown_signal().await;
+            shutdown::drain(health_reporter, inner, grace, stop_tx).await;
+        }
+    });
+
+    // the gateway serves the same todos, so it gets the same TLS and client certificate check
+    let mut acceptors = None;
+    match tls {
+        Some(tls) => {
+            acceptors = Some((tls.grpc_acceptor()?, tls.acceptor()?));
+            let mode = if tls.is_mutual() { "mutual TLS" } else { "TLS" };
+            info!("Server listening on {} with {}", config.addr, mode);
+            info!(
+                "REST gateway listening on {} with {}",
+                config.http_addr, mode
+            );
+        }
+        None => {
+            info!("Server listening on {}", config.addr);
+            info!("REST gateway listening on {}", config.http_addr);
+        }
+    }
+    info!("Metrics listening on {}", config.metrics_addr);
+
+    let metrics = Metrics::default();
+    let telemetry = metrics.layer();
+
+    let todos_service = TodosServer::with_interceptor(inner.clone(), auth.clone());
+    let grpc = Server::builder()
+        // gRPC-Web clients talk HTTP/1.1
+        .accept_http1(true)
+        .add_service(tonic_web::enable(telemetry.layer(health_service)))
+        .add_service(tonic_web::enable(telemetry.layer(todos_service)))
+        .add_service(telemetry.layer(reflection_service));
+
+    let rest_app = gateway::router(inner, auth).into_make_service();
+
+    let (grpc, rest) = match acceptors {
+        Some((grpc_acceptor, rest_acceptor)) => {
+            let handshake_timeout = Duration::from_secs(config.tls_handshake_timeout);
+            let grpc_listener = TcpListener::bind(config.addr).await?;
+            let rest_listener = TcpListener::bind(config.http_addr).await?;
+            (
+                grpc.serve_with_incoming_shutdown(
+                    tls::incoming(grpc_listener, grpc_acceptor, handshake_timeout),
+                    stopped(),
+                )
+                .boxed(),
+                axum::Server::builder(accept::from_stream(tls::incoming(
+                    rest_listener,
+                    rest_acceptor,
+                    handshake_timeout,
+                )))
+                .serve(rest_app)
+                .with_graceful_shutdown(stopped())
+                .boxed(),
+            )
+        }
+        None => (
+            grpc.serve_with_shutdown(config.addr, stopped()).boxed(),
+            axum::Server::bind(&config.http_addr)
+                .serve(rest_app)
+                .with_graceful_shutdown(stopped())
+                .boxed(),
+        ),
+    };
+
+    let metrics = axum::Server::bind(&config.metrics_addr)
+        .serve(metrics_router(metrics).into_make_service())
+        .with_graceful_shutdown(stopped());
+
+    let (grpc, rest, metrics) = tokio::join!(grpc, rest, metrics);
+    grpc?;
+    rest?;
+    metrics?;
+
+    info!("Server stopped");
 
     Ok(())
 }
+
+/// Resolves on Ctrl-C or, on unix, on SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
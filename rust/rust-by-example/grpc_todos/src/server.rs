@@ -1,131 +1,175 @@
-use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use futures::Stream;
-use tokio::sync::{mpsc, Mutex};
+use prost_types::Timestamp;
+use tokio::sync::{mpsc, watch};
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use tonic::{Response, Status};
+use tonic::{Code, Response, Status};
 
-use crate::{todos_server::Todos, Todo, TodoChangeResponse};
+use crate::{
+    auth::User,
+    store::{SharedStore, TodoStore},
+    sync_response::Outcome,
+    todo_change::Change,
+    todos_server::Todos,
+    validation::{check_transition, validate_status_update, validate_todo, validate_todo_update},
+    ConflictPolicy, SyncConflict, SyncRequest, SyncResponse, Todo, TodoBatchResponse,
+    TodoChangeResponse, TodoChangeResult, TodoDescriptor, TodoIdentifier, TodoListResponse,
+    TodoStatus, TodoUpdateRequest,
+};
 
+/// Todos are kept per user, see [`User::from_request`].
+///
+/// Clones share the same todos, so a clone can be kept to call [`TodoService::shutdown`]
+/// after the service has been handed to the server.
+#[derive(Clone)]
 pub struct TodoService {
-    todos: Arc<Mutex<HashMap<u32, Todo>>>,
+    todos: SharedStore,
+    shutdown: Arc<watch::Sender<bool>>,
 }
 
 impl Default for TodoService {
     fn default() -> Self {
         Self {
-            todos: Arc::new(Mutex::new(HashMap::new())),
+            todos: SharedStore::default(),
+            shutdown: Arc::new(watch::channel(false).0),
         }
     }
 }
 
+impl TodoService {
+    /// Ends every active `Watch` and `Sync` stream with `UNAVAILABLE` and rejects new ones,
+    /// so a graceful server shutdown does not wait on streams that never finish.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+}
+
 #[tonic::async_trait]
 impl Todos for TodoService {
-    #[must_use]
     #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     async fn add(
         &self,
         request: tonic::Request<super::Todo>,
     ) -> Result<Response<TodoChangeResponse>, Status> {
+        let user = User::from_request(&request);
         let todo = request.into_inner();
 
-        let identifier = match todo.id.clone() {
-            Some(id) => id,
-            None => return Err(Status::invalid_argument("id is required")),
-        };
+        let mut store = self.todos.write();
+        let identifier = apply_add(&mut store, &user, todo)?;
 
-        let mut map = self.todos.lock().await;
-
-        match map.get(&identifier.id) {
-            Some(_) => return Err(Status::already_exists("todo already exists")),
-            None => {
-                map.insert(identifier.id, todo);
-                return Ok(Response::new(TodoChangeResponse {
-                    id: Some(identifier),
-                    message: "todo added".into(),
-                }));
-            }
-        }
+        Ok(Response::new(TodoChangeResponse {
+            id: Some(identifier),
+            message: "todo added".into(),
+        }))
     }
 
-    #[must_use]
     #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     async fn remove(
         &self,
         request: tonic::Request<super::TodoIdentifier>,
     ) -> Result<Response<TodoChangeResponse>, Status> {
+        let user = User::from_request(&request);
         let request = request.into_inner();
-        let mut map = self.todos.lock().await;
-
-        match map.get(&request.id) {
-            Some(_) => {
-                map.remove(&request.id);
-                return Ok(Response::new(TodoChangeResponse {
-                    id: Some(request),
-                    message: "todo removed".into(),
-                }));
-            }
-            None => return Err(Status::not_found("todo not found")),
-        }
+
+        let mut store = self.todos.write();
+        let identifier = apply_remove(&mut store, &user, request)?;
+
+        Ok(Response::new(TodoChangeResponse {
+            id: Some(identifier),
+            message: "todo removed".into(),
+        }))
     }
 
-    #[must_use]
     #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     async fn update(
         &self,
         request: tonic::Request<super::TodoStatusUpdateRequest>,
     ) -> Result<Response<TodoChangeResponse>, Status> {
+        let user = User::from_request(&request);
         let request = request.into_inner();
-        let mut map = self.todos.lock().await;
+        validate_status_update(&request)?;
 
         let identifier = match request.id {
             Some(id) => id,
             None => return Err(Status::invalid_argument("id is required")),
         };
 
-        match map.get_mut(&identifier.id) {
-            Some(todo) => {
-                todo.status = request.status;
-                return Ok(Response::new(TodoChangeResponse {
-                    id: Some(identifier),
-                    message: "todo updated".into(),
-                }));
-            }
-            None => return Err(Status::not_found("todo not found")),
+        let mut store = self.todos.write();
+        let current = store.get(&user, identifier.id)?.status;
+
+        check_transition(identifier.id, current, request.status)?;
+        if is_completing(current, request.status) {
+            store.check_can_complete(&user, identifier.id)?;
         }
+        let todo = store.get_mut(&user, identifier.id)?;
+        todo.status = request.status;
+        todo.revision += 1;
+
+        Ok(Response::new(TodoChangeResponse {
+            id: Some(identifier),
+            message: "todo updated".into(),
+        }))
     }
 
-    #[must_use]
     #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     async fn get(
         &self,
         request: tonic::Request<super::TodoIdentifier>,
     ) -> Result<Response<Todo>, Status> {
+        let user = User::from_request(&request);
         let request = request.into_inner();
-        let map = self.todos.lock().await;
 
-        match map.get(&request.id) {
-            Some(todo) => return Ok(Response::new(todo.clone())),
-            None => return Err(Status::not_found("todo not found")),
-        }
+        let store = self.todos.read();
+        let todo = store.get(&user, request.id)?;
+
+        Ok(Response::new(todo.clone()))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    async fn list(
+        &self,
+        request: tonic::Request<super::TodoListRequest>,
+    ) -> Result<Response<TodoListResponse>, Status> {
+        let user = User::from_request(&request);
+        let request = request.into_inner();
+        let now = Timestamp::from(SystemTime::now());
+
+        let store = self.todos.read();
+        let todos = store
+            .list(&user)
+            .into_iter()
+            .filter(|todo| match &request.tag {
+                Some(tag) => todo.tags.contains(tag),
+                None => true,
+            })
+            .filter(|todo| !request.overdue || is_overdue(todo, &now))
+            .collect();
+
+        Ok(Response::new(TodoListResponse { todos }))
     }
 
     #[doc = " Server streaming response type for the Watch method."]
     type WatchStream = Pin<Box<dyn Stream<Item = Result<Todo, Status>> + Send + Sync>>;
 
-    #[must_use]
     #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
     async fn watch(
         &self,
         request: tonic::Request<super::TodoIdentifier>,
     ) -> Result<Response<Self::WatchStream>, Status> {
+        let user = User::from_request(&request);
         let request = request.into_inner();
-        let map = self.todos.lock().await;
 
-        let mut previous_todo = match map.get(&request.id) {
-            Some(todo) => todo.clone(),
-            None => return Err(Status::not_found("todo not found")),
-        };
+        let mut shutdown = self.shutdown.subscribe();
+        if *shutdown.borrow() {
+            return Err(Status::unavailable("server is shutting down"));
+        }
+
+        let mut previous_todo = self.todos.read().get(&user, request.id)?.clone();
 
         let (tx, rx) = mpsc::unbounded_channel();
 
@@ -133,20 +177,30 @@ impl Todos for TodoService {
 
         tokio::spawn(async move {
             loop {
-                tokio::time::sleep(Duration::from_secs(1));
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = shutdown.changed() => {
+                        let _ = tx.send(Err(Status::unavailable("server is shutting down")));
+                        return;
+                    }
+                    // the client went away
+                    _ = tx.closed() => return,
+                }
 
-                let map = todos.lock().await;
+                let store = todos.read();
 
-                let new_todo = match map.get(&request.id) {
-                    Some(todo) => todo.clone(),
-                    None => {
-                        tx.send(Err(Status::not_found("todo not found"))).unwrap();
+                let new_todo = match store.get(&user, request.id) {
+                    Ok(todo) => todo.clone(),
+                    Err(status) => {
+                        let _ = tx.send(Err(status));
                         return;
                     }
                 };
 
                 if new_todo != previous_todo {
-                    tx.send(Ok(new_todo.clone())).unwrap();
+                    if tx.send(Ok(new_todo.clone())).is_err() {
+                        return;
+                    }
                     previous_todo = new_todo;
                 }
             }
@@ -156,4 +210,437 @@ impl Todos for TodoService {
 
         Ok(Response::new(Box::pin(stream)))
     }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    async fn update_todo(
+        &self,
+        request: tonic::Request<super::TodoUpdateRequest>,
+    ) -> Result<Response<TodoChangeResponse>, Status> {
+        let user = User::from_request(&request);
+        let request = request.into_inner();
+
+        let mut store = self.todos.write();
+        let identifier = apply_update(&mut store, &user, request)?;
+
+        Ok(Response::new(TodoChangeResponse {
+            id: Some(identifier),
+            message: "todo updated".into(),
+        }))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    async fn batch_add(
+        &self,
+        request: tonic::Request<super::TodoBatchAddRequest>,
+    ) -> Result<Response<TodoBatchResponse>, Status> {
+        let user = User::from_request(&request);
+        let changes: Vec<_> = request
+            .into_inner()
+            .todos
+            .into_iter()
+            .map(|todo| Some(Change::Add(todo)))
+            .collect();
+        check_batch_size(changes.len())?;
+
+        let mut store = self.todos.write();
+        Ok(Response::new(apply_batch(&mut store, &user, changes)))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    async fn batch_update(
+        &self,
+        request: tonic::Request<super::TodoBatchUpdateRequest>,
+    ) -> Result<Response<TodoBatchResponse>, Status> {
+        let user = User::from_request(&request);
+        let changes: Vec<_> = request
+            .into_inner()
+            .updates
+            .into_iter()
+            .map(|update| Some(Change::Update(update)))
+            .collect();
+        check_batch_size(changes.len())?;
+
+        let mut store = self.todos.write();
+        Ok(Response::new(apply_batch(&mut store, &user, changes)))
+    }
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    async fn import(
+        &self,
+        request: tonic::Request<tonic::Streaming<super::TodoChange>>,
+    ) -> Result<Response<TodoBatchResponse>, Status> {
+        let user = User::from_request(&request);
+        let mut stream = request.into_inner();
+
+        // the store is only locked once the whole batch has been received
+        let mut changes = Vec::new();
+        while let Some(change) = stream.message().await? {
+            changes.push(change.change);
+            check_batch_size(changes.len())?;
+        }
+
+        let mut store = self.todos.write();
+        Ok(Response::new(apply_batch(&mut store, &user, changes)))
+    }
+
+    #[doc = " Server streaming response type for the Sync method."]
+    type SyncStream = Pin<Box<dyn Stream<Item = Result<SyncResponse, Status>> + Send + Sync>>;
+
+    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
+    async fn sync(
+        &self,
+        request: tonic::Request<tonic::Streaming<super::SyncRequest>>,
+    ) -> Result<Response<Self::SyncStream>, Status> {
+        let user = User::from_request(&request);
+        let mut requests = request.into_inner();
+
+        let mut shutdown = self.shutdown.subscribe();
+        if *shutdown.borrow() {
+            return Err(Status::unavailable("server is shutting down"));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let todos = self.todos.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let request = tokio::select! {
+                    request = requests.message() => request,
+                    _ = shutdown.changed() => {
+                        let _ = tx.send(Err(Status::unavailable("server is shutting down")));
+                        return;
+                    }
+                };
+
+                let request = match request {
+                    Ok(Some(request)) => request,
+                    // the client has sent all its changes
+                    Ok(None) => return,
+                    Err(status) => {
+                        let _ = tx.send(Err(status));
+                        return;
+                    }
+                };
+
+                let response = apply_sync(&mut todos.write(), &user, request);
+                if tx.send(Ok(response)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let stream = UnboundedReceiverStream::new(rx);
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Maximum number of changes in a single batch.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+#[allow(clippy::result_large_err)]
+fn check_batch_size(len: usize) -> Result<(), Status> {
+    if len > MAX_BATCH_SIZE {
+        return Err(Status::invalid_argument(format!(
+            "batches are limited to {} changes",
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::result_large_err)]
+fn apply_add(store: &mut TodoStore, user: &User, mut todo: Todo) -> Result<TodoIdentifier, Status> {
+    validate_todo(&todo)?;
+    todo.revision = 0;
+
+    let identifier = match todo.id.clone() {
+        Some(id) => id,
+        None => return Err(Status::invalid_argument("id is required")),
+    };
+
+    if let Some(parent) = &todo.parent_id {
+        store.check_parent(user, identifier.id, parent.id)?;
+    }
+    store.insert(user, identifier.id, todo)?;
+
+    Ok(identifier)
+}
+
+#[allow(clippy::result_large_err)]
+fn apply_update(
+    store: &mut TodoStore,
+    user: &User,
+    request: TodoUpdateRequest,
+) -> Result<TodoIdentifier, Status> {
+    validate_todo_update(&request)?;
+
+    let patch = match request.todo {
+        Some(todo) => todo,
+        None => return Err(Status::invalid_argument("todo is required")),
+    };
+
+    let identifier = match patch.id.clone() {
+        Some(id) => id,
+        None => return Err(Status::invalid_argument("id is required")),
+    };
+
+    let paths = request
+        .update_mask
+        .map(|mask| mask.paths)
+        .unwrap_or_default();
+
+    let current = store.get(user, identifier.id)?;
+
+    let mut updated = current.clone();
+    apply_update_mask(&mut updated, patch, &paths);
+    updated.revision = current.revision + 1;
+
+    check_transition(identifier.id, current.status, updated.status)?;
+    if is_completing(current.status, updated.status) {
+        store.check_can_complete(user, identifier.id)?;
+    }
+    if updated.parent_id != current.parent_id {
+        if let Some(parent) = &updated.parent_id {
+            store.check_parent(user, identifier.id, parent.id)?;
+        }
+    }
+
+    *store.get_mut(user, identifier.id)? = updated;
+    Ok(identifier)
+}
+
+#[allow(clippy::result_large_err)]
+fn apply_remove(
+    store: &mut TodoStore,
+    user: &User,
+    identifier: TodoIdentifier,
+) -> Result<TodoIdentifier, Status> {
+    store.remove(user, identifier.id)?;
+    Ok(identifier)
+}
+
+/// Applies `changes` in order, rolling the todos of `user` back to a snapshot unless every
+/// change succeeded.
+fn apply_batch(
+    store: &mut TodoStore,
+    user: &User,
+    changes: Vec<Option<Change>>,
+) -> TodoBatchResponse {
+    let snapshot = store.snapshot(user);
+
+    let outcomes: Vec<_> = changes
+        .into_iter()
+        .map(|change| {
+            let id = change.as_ref().and_then(target);
+            let outcome = match change {
+                Some(Change::Add(todo)) => apply_add(store, user, todo),
+                Some(Change::Update(update)) => apply_update(store, user, update),
+                Some(Change::Remove(id)) => apply_remove(store, user, id),
+                None => Err(Status::invalid_argument("change is required")),
+            };
+            (id, outcome)
+        })
+        .collect();
+
+    let applied = outcomes.iter().all(|(_, outcome)| outcome.is_ok());
+    if !applied {
+        store.restore(snapshot);
+    }
+
+    let results = outcomes
+        .into_iter()
+        .map(|(id, outcome)| {
+            let (code, message) = match outcome {
+                Ok(_) if applied => (Code::Ok, "ok".to_string()),
+                Ok(_) => (
+                    Code::Aborted,
+                    "not applied because another change failed".to_string(),
+                ),
+                Err(status) => (status.code(), status.message().to_string()),
+            };
+            TodoChangeResult {
+                id,
+                code: code as i32,
+                message,
+            }
+        })
+        .collect();
+
+    TodoBatchResponse { applied, results }
+}
+
+/// Applies a single `Sync` change, checking its base revision against the stored todo.
+///
+/// Adding a todo that already exists is a conflict too, resolved by replacing the stored
+/// fields under last-writer-wins. Removing a todo that is already gone is accepted.
+#[allow(clippy::result_large_err)]
+fn apply_sync(store: &mut TodoStore, user: &User, request: SyncRequest) -> SyncResponse {
+    let reject = request.policy() == ConflictPolicy::Reject;
+    let base_revision = request.base_revision;
+    let change = request.change.and_then(|change| change.change);
+    let id = change.as_ref().and_then(target);
+
+    let conflict = |current: Option<&Todo>| {
+        Outcome::Conflict(SyncConflict {
+            id: id.clone(),
+            base_revision,
+            current: current.cloned(),
+        })
+    };
+
+    let outcome = match change {
+        Some(Change::Add(todo)) => match id.as_ref().map(|id| store.get(user, id.id)) {
+            Some(Ok(current)) if reject => Ok(conflict(Some(current))),
+            Some(Ok(_)) => {
+                let replace = TodoUpdateRequest {
+                    todo: Some(todo),
+                    update_mask: None,
+                };
+                apply_update(store, user, replace).and_then(|id| accepted(store, user, id))
+            }
+            _ => apply_add(store, user, todo).and_then(|id| accepted(store, user, id)),
+        },
+        Some(Change::Update(update)) => match id.as_ref().map(|id| store.get(user, id.id)) {
+            Some(Err(status)) if status.code() == Code::NotFound => Ok(conflict(None)),
+            Some(Ok(current)) if reject && current.revision != base_revision => {
+                Ok(conflict(Some(current)))
+            }
+            _ => apply_update(store, user, update).and_then(|id| accepted(store, user, id)),
+        },
+        Some(Change::Remove(identifier)) => match store.get(user, identifier.id) {
+            Err(status) if status.code() == Code::NotFound => Ok(Outcome::Removed(identifier)),
+            Ok(current) if reject && current.revision != base_revision => {
+                Ok(conflict(Some(current)))
+            }
+            _ => apply_remove(store, user, identifier).map(Outcome::Removed),
+        },
+        None => Err(Status::invalid_argument("change is required")),
+    };
+
+    let outcome = outcome.unwrap_or_else(|status| {
+        Outcome::Error(TodoChangeResult {
+            id,
+            code: status.code() as i32,
+            message: status.message().to_string(),
+        })
+    });
+
+    SyncResponse {
+        change_id: request.change_id,
+        outcome: Some(outcome),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn accepted(store: &TodoStore, user: &User, id: TodoIdentifier) -> Result<Outcome, Status> {
+    Ok(Outcome::Accepted(store.get(user, id.id)?.clone()))
+}
+
+/// The id of the todo `change` applies to.
+fn target(change: &Change) -> Option<TodoIdentifier> {
+    match change {
+        Change::Add(todo) => todo.id.clone(),
+        Change::Update(update) => update.todo.as_ref().and_then(|todo| todo.id.clone()),
+        Change::Remove(id) => Some(id.clone()),
+    }
+}
+
+/// Copies the fields named by `paths` from `patch` into `todo`.
+///
+/// An empty mask replaces every field but the id.
+/// The paths must already have been checked by [`validate_todo_update`].
+fn apply_update_mask(todo: &mut Todo, patch: Todo, paths: &[String]) {
+    if paths.is_empty() {
+        *todo = Todo {
+            id: todo.id.take(),
+            ..patch
+        };
+        return;
+    }
+
+    let descriptor = patch.descriptor.clone().unwrap_or_default();
+
+    for path in paths {
+        match path.as_str() {
+            "status" => todo.status = patch.status,
+            "due_at" => todo.due_at = patch.due_at.clone(),
+            "priority" => todo.priority = patch.priority,
+            "tags" => todo.tags = patch.tags.clone(),
+            "parent_id" => todo.parent_id = patch.parent_id.clone(),
+            "descriptor" => todo.descriptor = Some(descriptor.clone()),
+            "descriptor.title" => {
+                todo.descriptor
+                    .get_or_insert_with(TodoDescriptor::default)
+                    .title = descriptor.title.clone();
+            }
+            "descriptor.description" => {
+                todo.descriptor
+                    .get_or_insert_with(TodoDescriptor::default)
+                    .description = descriptor.description.clone();
+            }
+            _ => unreachable!("update mask paths are validated before they are applied"),
+        }
+    }
+}
+
+fn is_completing(from: i32, to: i32) -> bool {
+    from != TodoStatus::Completed as i32 && to == TodoStatus::Completed as i32
+}
+
+/// A todo is overdue once its due date has passed without it being completed.
+fn is_overdue(todo: &Todo, now: &Timestamp) -> bool {
+    todo.status != TodoStatus::Completed as i32
+        && todo
+            .due_at
+            .as_ref()
+            .is_some_and(|due| (due.seconds, due.nanos) < (now.seconds, now.nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TodoIdentifier;
+
+    fn todo(title: &str, description: Option<&str>) -> Todo {
+        Todo {
+            id: Some(TodoIdentifier { id: 1 }),
+            status: TodoStatus::New.into(),
+            descriptor: Some(TodoDescriptor {
+                title: title.into(),
+                description: description.map(Into::into),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn update_mask_patches_only_listed_fields() {
+        let mut current = todo("write docs", Some("for the todo service"));
+        let mut patch = todo("write tests", None);
+        patch.status = TodoStatus::Ongoing.into();
+
+        apply_update_mask(&mut current, patch, &["descriptor.title".into()]);
+
+        let descriptor = current.descriptor.unwrap();
+        assert_eq!(descriptor.title, "write tests");
+        assert_eq!(
+            descriptor.description.as_deref(),
+            Some("for the todo service")
+        );
+        assert_eq!(current.status, i32::from(TodoStatus::New));
+    }
+
+    #[test]
+    fn empty_update_mask_replaces_mutable_fields() {
+        let mut current = todo("write docs", Some("for the todo service"));
+        let mut patch = todo("write tests", None);
+        patch.status = TodoStatus::Completed.into();
+
+        apply_update_mask(&mut current, patch.clone(), &[]);
+
+        assert_eq!(current, patch);
+    }
 }
@@ -0,0 +1,40 @@
+//! Health reporting over the server's lifetime, and the graceful shutdown sequence.
+//!
+//! A load balancer polling `grpc.health.v1.Health` has to see `NOT_SERVING` before the server
+//! stops accepting connections, or it keeps routing new calls to a server that is going away.
+//! [`drain`] therefore reports `NOT_SERVING` first and only stops the server after a grace
+//! period.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tonic_health::server::HealthReporter;
+use tracing::info;
+
+use crate::{server::TodoService, todos_server::TodosServer};
+
+/// How long [`drain`] keeps serving after reporting `NOT_SERVING`, unless configured.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(5);
+
+/// Reports `todos.Todos` as `SERVING`.
+pub async fn report_serving(health: &mut HealthReporter) {
+    health.set_serving::<TodosServer<TodoService>>().await;
+}
+
+/// Winds the server down: reports `NOT_SERVING`, keeps serving for `grace` so health checks
+/// pick that up, then ends the `Watch` and `Sync` streams of `service` and sends `true` on
+/// `stop` for the listeners to stop and drain their in-flight requests.
+pub async fn drain(
+    mut health: HealthReporter,
+    service: TodoService,
+    grace: Duration,
+    stop: watch::Sender<bool>,
+) {
+    health.set_not_serving::<TodosServer<TodoService>>().await;
+    info!("Reporting NOT_SERVING, stopping in {:?}", grace);
+    tokio::time::sleep(grace).await;
+
+    info!("Shutting down, draining active requests");
+    service.shutdown();
+    let _ = stop.send(true);
+}
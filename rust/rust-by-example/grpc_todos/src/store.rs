@@ -0,0 +1,203 @@
+//! In-memory todo storage partitioned by user.
+
+#![allow(clippy::result_large_err)]
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use tonic::Status;
+use tonic_types::PreconditionViolation;
+
+use crate::{auth::User, validation::precondition_failed, Todo, TodoIdentifier, TodoStatus};
+
+/// A [`TodoStore`] shared by all requests.
+///
+/// Reads take a shared lock, so `Get`, `List` and the `Watch` polls run in parallel and only
+/// changes are serialized. The lock is never held across an `.await`, which is why a blocking
+/// lock is used rather than an async one.
+#[derive(Clone, Default)]
+pub struct SharedStore(Arc<RwLock<TodoStore>>);
+
+impl SharedStore {
+    pub fn read(&self) -> RwLockReadGuard<'_, TodoStore> {
+        // changes are checked before anything is written, a panicking request cannot leave
+        // the store half updated
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, TodoStore> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Todos grouped by the user owning them.
+///
+/// Ids are unique across all users: touching an id that belongs to someone else fails
+/// with `PERMISSION_DENIED` rather than `NOT_FOUND`. Changes made on behalf of a user only
+/// ever write to that user's partition, which is what makes [`TodoStore::snapshot`] enough to
+/// roll them back.
+#[derive(Clone, Default)]
+pub struct TodoStore {
+    partitions: HashMap<User, HashMap<u32, Todo>>,
+}
+
+/// The todos of one user at some point, to roll back to with [`TodoStore::restore`].
+pub struct Snapshot {
+    user: User,
+    todos: Option<HashMap<u32, Todo>>,
+}
+
+impl TodoStore {
+    /// Copies the todos of `user`, and only those, so a change to them can be undone.
+    pub fn snapshot(&self, user: &User) -> Snapshot {
+        Snapshot {
+            user: user.clone(),
+            todos: self.partitions.get(user).cloned(),
+        }
+    }
+
+    /// Puts the todos of the snapshot's user back as they were when it was taken.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        match snapshot.todos {
+            Some(todos) => self.partitions.insert(snapshot.user, todos),
+            None => self.partitions.remove(&snapshot.user),
+        };
+    }
+
+    pub fn get(&self, user: &User, id: u32) -> Result<&Todo, Status> {
+        match self.partitions.get(user).and_then(|todos| todos.get(&id)) {
+            Some(todo) => Ok(todo),
+            None => Err(self.missing(id)),
+        }
+    }
+
+    pub fn get_mut(&mut self, user: &User, id: u32) -> Result<&mut Todo, Status> {
+        self.get(user, id)?;
+
+        Ok(self
+            .partitions
+            .get_mut(user)
+            .and_then(|todos| todos.get_mut(&id))
+            .expect("todo was just looked up"))
+    }
+
+    pub fn insert(&mut self, user: &User, id: u32, todo: Todo) -> Result<(), Status> {
+        match self.owner(id) {
+            Some(owner) if owner == user => Err(Status::already_exists("todo already exists")),
+            Some(_) => Err(permission_denied()),
+            None => {
+                self.partitions
+                    .entry(user.clone())
+                    .or_default()
+                    .insert(id, todo);
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes a todo, turning its subtasks into top-level todos.
+    pub fn remove(&mut self, user: &User, id: u32) -> Result<Todo, Status> {
+        let todos = match self.partitions.get_mut(user) {
+            Some(todos) if todos.contains_key(&id) => todos,
+            _ => return Err(self.missing(id)),
+        };
+
+        let parent = Some(TodoIdentifier { id });
+        for todo in todos.values_mut().filter(|todo| todo.parent_id == parent) {
+            todo.parent_id = None;
+            todo.revision += 1;
+        }
+
+        Ok(todos.remove(&id).expect("todo was just looked up"))
+    }
+
+    /// Checks that `parent` is a todo of `user` that can hold `id` as a subtask
+    /// without creating a cycle.
+    pub fn check_parent(&self, user: &User, id: u32, parent: u32) -> Result<(), Status> {
+        let mut ancestor = match self.get(user, parent) {
+            Ok(todo) => Some(todo),
+            Err(status) if status.code() == tonic::Code::NotFound => {
+                return Err(parent_violation(parent, "parent todo does not exist"));
+            }
+            Err(status) => return Err(status),
+        };
+
+        while let Some(todo) = ancestor {
+            if todo.id.as_ref().map(|id| id.id) == Some(id) {
+                return Err(parent_violation(parent, "subtasks cannot form a cycle"));
+            }
+            ancestor = todo
+                .parent_id
+                .as_ref()
+                .and_then(|parent| self.get(user, parent.id).ok());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every subtask of `id` is completed.
+    pub fn check_can_complete(&self, user: &User, id: u32) -> Result<(), Status> {
+        let parent = Some(TodoIdentifier { id });
+        let violations: Vec<PreconditionViolation> = self
+            .list(user)
+            .into_iter()
+            .filter(|todo| todo.parent_id == parent && todo.status != TodoStatus::Completed as i32)
+            .map(|todo| {
+                PreconditionViolation::new(
+                    "OPEN_SUBTASK",
+                    format!("todos/{}", todo.id.map(|id| id.id).unwrap_or_default()),
+                    "subtask is not completed",
+                )
+            })
+            .collect();
+
+        if !violations.is_empty() {
+            return Err(precondition_failed("todo has open subtasks", violations));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the todos of `user` ordered by id.
+    pub fn list(&self, user: &User) -> Vec<Todo> {
+        let mut todos: Vec<Todo> = self
+            .partitions
+            .get(user)
+            .map(|todos| todos.values().cloned().collect())
+            .unwrap_or_default();
+        todos.sort_by_key(|todo| todo.id.as_ref().map(|id| id.id));
+        todos
+    }
+
+    fn owner(&self, id: u32) -> Option<&User> {
+        self.partitions
+            .iter()
+            .find(|(_, todos)| todos.contains_key(&id))
+            .map(|(user, _)| user)
+    }
+
+    /// The error for an id that is not in the caller's partition.
+    fn missing(&self, id: u32) -> Status {
+        match self.owner(id) {
+            Some(_) => permission_denied(),
+            None => Status::not_found("todo not found"),
+        }
+    }
+}
+
+fn parent_violation(parent: u32, description: &str) -> Status {
+    precondition_failed(
+        "invalid parent",
+        vec![PreconditionViolation::new(
+            "PARENT",
+            format!("todos/{}", parent),
+            description,
+        )],
+    )
+}
+
+fn permission_denied() -> Status {
+    Status::permission_denied("todo belongs to another user")
+}
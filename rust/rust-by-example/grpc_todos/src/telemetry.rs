@@ -0,0 +1,345 @@
+//! Tracing spans and Prometheus metrics for every RPC.
+//!
+//! [`TelemetryLayer`] wraps a gRPC service. Each call gets an `rpc` span carrying the method,
+//! and once the response has been fully sent, its gRPC status code and duration. The same
+//! numbers feed the [`Metrics`], which [`metrics_router`] exposes in the Prometheus text format.
+//!
+//! Metrics are labelled with the method only if it is one the server serves. The layer sits in
+//! front of authentication, so any caller could otherwise create a new series per made up
+//! method name; those calls are all counted under `unknown` instead.
+
+use std::{
+    collections::HashSet,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{extract::State, routing::get, Router};
+use bytes::Bytes;
+use http::{HeaderMap, Request, Response};
+use http_body::Body;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use tonic::{body::BoxBody, server::NamedService, Code, Status};
+use tower::{Layer, Service};
+use tracing::{field, info_span, Instrument, Span};
+
+/// The server streaming method counted by `todos_active_watch_streams`.
+const WATCH_METHOD: &str = "/todos.Todos/Watch";
+
+/// The `method` label of calls to methods none of the served services has.
+const UNKNOWN_METHOD: &str = "unknown";
+
+/// RPC metrics, shared by every service wrapped in a [`TelemetryLayer`] built from them.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    active_watches: IntGauge,
+    /// Paths of the methods labelled by name, `/package.Service/Method`.
+    methods: Arc<HashSet<String>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new("grpc_server_requests_total", "Finished RPCs"),
+            &["method", "code"],
+        )
+        .expect("valid metric");
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "grpc_server_request_duration_seconds",
+                "Time from receiving an RPC until its response was sent",
+            ),
+            &["method"],
+        )
+        .expect("valid metric");
+        let active_watches =
+            IntGauge::new("todos_active_watch_streams", "Watch streams currently open")
+                .expect("valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(requests.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(latency.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(active_watches.clone()))
+            .expect("metric registered once");
+
+        // the services the server is built with: todos, health and reflection
+        let methods = [
+            crate::FILE_DESCRIPTOR_SET,
+            tonic_health::pb::FILE_DESCRIPTOR_SET,
+            tonic_reflection::pb::FILE_DESCRIPTOR_SET,
+        ]
+        .into_iter()
+        .flat_map(method_paths)
+        .collect();
+
+        Self {
+            registry,
+            requests,
+            latency,
+            active_watches,
+            methods: Arc::new(methods),
+        }
+    }
+}
+
+impl Metrics {
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics are valid text");
+        String::from_utf8(buffer).expect("metrics are valid text")
+    }
+
+    /// The `method` label for a call to `path`.
+    fn method_label(&self, path: &str) -> String {
+        if self.methods.contains(path) {
+            path.to_string()
+        } else {
+            UNKNOWN_METHOD.to_string()
+        }
+    }
+
+    pub fn layer(&self) -> TelemetryLayer {
+        TelemetryLayer {
+            metrics: self.clone(),
+        }
+    }
+}
+
+/// Serves `GET /metrics` for Prometheus to scrape.
+pub fn metrics_router(metrics: Metrics) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(metrics)
+}
+
+async fn render(State(metrics): State<Metrics>) -> String {
+    metrics.render()
+}
+
+#[derive(Clone)]
+pub struct TelemetryLayer {
+    metrics: Metrics,
+}
+
+impl<S> Layer<S> for TelemetryLayer {
+    type Service = Telemetry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Telemetry {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// A gRPC service wrapped by [`TelemetryLayer`].
+#[derive(Clone)]
+pub struct Telemetry<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S: NamedService> NamedService for Telemetry<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> Service<Request<B>> for Telemetry<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let path = request.uri().path();
+        let span = info_span!(
+            "rpc",
+            method = %path,
+            code = field::Empty,
+            duration_ms = field::Empty,
+        );
+        let method = self.metrics.method_label(path);
+        let mut call = Call::start(self.metrics.clone(), method, span.clone());
+        let response = self.inner.call(request);
+
+        Box::pin(
+            async move {
+                let response = match response.await {
+                    Ok(response) => response,
+                    Err(err) => {
+                        call.finish(Code::Internal);
+                        return Err(err);
+                    }
+                };
+
+                // errors returned by the handler come as a trailers-only response
+                if let Some(code) = grpc_status(response.headers()) {
+                    call.finish(code);
+                }
+
+                Ok(response.map(|body| TelemetryBody { inner: body, call }.boxed_unsync()))
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// An RPC in progress, recorded once its outcome is known.
+struct Call {
+    metrics: Metrics,
+    method: String,
+    span: Span,
+    start: Instant,
+    finished: bool,
+}
+
+impl Call {
+    fn start(metrics: Metrics, method: String, span: Span) -> Self {
+        if method == WATCH_METHOD {
+            metrics.active_watches.inc();
+        }
+
+        Self {
+            metrics,
+            method,
+            span,
+            start: Instant::now(),
+            finished: false,
+        }
+    }
+
+    fn finish(&mut self, code: Code) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        let elapsed = self.start.elapsed();
+        let code = format!("{:?}", code);
+        self.span.record("code", code.as_str());
+        self.span
+            .record("duration_ms", elapsed.as_secs_f64() * 1000.0);
+        tracing::info!(parent: &self.span, "finished");
+
+        self.metrics
+            .requests
+            .with_label_values(&[&self.method, &code])
+            .inc();
+        self.metrics
+            .latency
+            .with_label_values(&[&self.method])
+            .observe(elapsed.as_secs_f64());
+        if self.method == WATCH_METHOD {
+            self.metrics.active_watches.dec();
+        }
+    }
+}
+
+impl Drop for Call {
+    // the response was dropped before its trailers, e.g. when a client closes a stream
+    fn drop(&mut self) {
+        self.finish(Code::Cancelled);
+    }
+}
+
+/// Passes the response body through, recording the call when the trailers go by.
+struct TelemetryBody {
+    inner: BoxBody,
+    call: Call,
+}
+
+impl Body for TelemetryBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Err(status))) = &poll {
+            let code = status.code();
+            self.call.finish(code);
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let poll = Pin::new(&mut self.inner).poll_trailers(cx);
+        match &poll {
+            Poll::Ready(Ok(trailers)) => {
+                let code = trailers
+                    .as_ref()
+                    .and_then(grpc_status)
+                    .unwrap_or(Code::Unknown);
+                self.call.finish(code);
+            }
+            Poll::Ready(Err(status)) => {
+                let code = status.code();
+                self.call.finish(code);
+            }
+            Poll::Pending => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The paths of every method in an encoded file descriptor set.
+fn method_paths(encoded: &[u8]) -> Vec<String> {
+    let set = FileDescriptorSet::decode(encoded).expect("descriptor set is valid");
+    let mut paths = Vec::new();
+    for file in &set.file {
+        for service in &file.service {
+            for method in &service.method {
+                paths.push(format!(
+                    "/{}.{}/{}",
+                    file.package(),
+                    service.name(),
+                    method.name()
+                ));
+            }
+        }
+    }
+    paths
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<Code> {
+    headers
+        .get("grpc-status")
+        .map(|status| Code::from_bytes(status.as_bytes()))
+}
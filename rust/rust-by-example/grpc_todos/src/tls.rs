@@ -0,0 +1,152 @@
+//! TLS settings for the gRPC server and the REST gateway.
+//!
+//! The server certificate and key are PEM files. Giving a client CA as well turns on mutual
+//! TLS: clients must then present a certificate signed by that CA to connect. Both listeners
+//! are served from the same files and accept connections through [`incoming`], so the gateway
+//! cannot be used to get around the client certificate check and neither listener waits on a
+//! stalled handshake forever.
+
+use std::{fs, io, path::Path, path::PathBuf, sync::Arc, time::Duration};
+
+use futures::Stream;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{
+    rustls::{self, server::AllowAnyAuthenticatedClient, RootCertStore},
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, warn};
+
+/// How long a client gets to complete the TLS handshake before it is disconnected.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// PEM files the server's TLS configuration is loaded from.
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA that client certificates must be signed by; mutual TLS is off without it.
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsFiles {
+    /// Reads the files into an acceptor for the gRPC server, which speaks HTTP/2 and, for
+    /// gRPC-Web clients, HTTP/1.1.
+    pub fn grpc_acceptor(&self) -> io::Result<TlsAcceptor> {
+        self.acceptor_for(&[b"h2", b"http/1.1"])
+    }
+
+    /// Reads the files into an acceptor for the HTTP/1.1 listeners, such as the REST gateway.
+    pub fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        self.acceptor_for(&[b"http/1.1"])
+    }
+
+    fn acceptor_for(&self, alpn_protocols: &[&[u8]]) -> io::Result<TlsAcceptor> {
+        let builder = rustls::ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in certs(client_ca)? {
+                    roots.add(&cert).map_err(|err| invalid(client_ca, err))?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(certs(&self.cert)?, private_key(&self.key)?)
+            .map_err(|err| invalid(&self.key, err))?;
+        config.alpn_protocols = alpn_protocols.iter().map(|alpn| alpn.to_vec()).collect();
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    pub fn is_mutual(&self) -> bool {
+        self.client_ca.is_some()
+    }
+}
+
+/// Accepts connections on `listener` and completes their TLS handshake with `acceptor`,
+/// ready for [`tonic::transport::server::Router::serve_with_incoming`] or, through
+/// [`hyper::server::accept::from_stream`], for [`hyper::Server::builder`].
+///
+/// Handshakes run on their own tasks, so a client that stalls in one does not hold up the
+/// others, and are given up on after `handshake_timeout`. Connections failing the handshake,
+/// such as plaintext ones or ones without an acceptable client certificate, are dropped.
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+) -> impl Stream<Item = io::Result<TlsStream<TcpStream>>> {
+    let (tx, rx) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                // the server has stopped
+                _ = tx.closed() => return,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        // such as running out of file descriptors; hyper would stop serving
+                        // on an error, so back off and try again instead
+                        warn!("Failed to accept a connection: {}", err);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                },
+            };
+
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(handshake_timeout, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(err)) => debug!(%peer, "TLS handshake failed: {}", err),
+                    Err(_) => debug!(%peer, "TLS handshake timed out"),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+}
+
+fn certs(path: &Path) -> io::Result<Vec<rustls::Certificate>> {
+    let certs = rustls_pemfile::certs(&mut read(path)?.as_slice())?;
+    if certs.is_empty() {
+        return Err(invalid(path, "no certificate found"));
+    }
+    Ok(certs.into_iter().map(rustls::Certificate).collect())
+}
+
+fn private_key(path: &Path) -> io::Result<rustls::PrivateKey> {
+    let pem = read(path)?;
+    for item in rustls_pemfile::read_all(&mut pem.as_slice())? {
+        if let rustls_pemfile::Item::PKCS8Key(key)
+        | rustls_pemfile::Item::RSAKey(key)
+        | rustls_pemfile::Item::ECKey(key) = item
+        {
+            return Ok(rustls::PrivateKey(key));
+        }
+    }
+    Err(invalid(path, "no private key found"))
+}
+
+fn invalid(path: &Path, err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), err),
+    )
+}
@@ -22,6 +22,134 @@ pub struct TodoStatusUpdateRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoUpdateRequest {
+    #[prost(message, optional, tag = "1")]
+    pub todo: ::core::option::Option<Todo>,
+    #[prost(message, optional, tag = "2")]
+    pub update_mask: ::core::option::Option<::prost_types::FieldMask>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoBatchAddRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub todos: ::prost::alloc::vec::Vec<Todo>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoBatchUpdateRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub updates: ::prost::alloc::vec::Vec<TodoUpdateRequest>,
+}
+/// A single change streamed to Import.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoChange {
+    #[prost(oneof = "todo_change::Change", tags = "1, 2, 3")]
+    pub change: ::core::option::Option<todo_change::Change>,
+}
+/// Nested message and enum types in `TodoChange`.
+pub mod todo_change {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Change {
+        #[prost(message, tag = "1")]
+        Add(super::Todo),
+        #[prost(message, tag = "2")]
+        Update(super::TodoUpdateRequest),
+        #[prost(message, tag = "3")]
+        Remove(super::TodoIdentifier),
+    }
+}
+/// Outcome of one change of a batch, in the order the changes were sent.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoChangeResult {
+    #[prost(message, optional, tag = "1")]
+    pub id: ::core::option::Option<TodoIdentifier>,
+    /// gRPC status code, 0 (OK) on success
+    #[prost(int32, tag = "2")]
+    pub code: i32,
+    #[prost(string, tag = "3")]
+    pub message: ::prost::alloc::string::String,
+}
+/// Batches are all-or-nothing: either every change is applied or none is.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoBatchResponse {
+    #[prost(bool, tag = "1")]
+    pub applied: bool,
+    #[prost(message, repeated, tag = "2")]
+    pub results: ::prost::alloc::vec::Vec<TodoChangeResult>,
+}
+/// A change a client made while offline.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SyncRequest {
+    /// echoed in the response so the client can match outcomes to its changes
+    #[prost(string, tag = "1")]
+    pub change_id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub change: ::core::option::Option<TodoChange>,
+    /// revision of the todo the change was made against, ignored for adds
+    #[prost(uint64, tag = "3")]
+    pub base_revision: u64,
+    #[prost(enumeration = "ConflictPolicy", tag = "4")]
+    pub policy: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SyncConflict {
+    #[prost(message, optional, tag = "1")]
+    pub id: ::core::option::Option<TodoIdentifier>,
+    #[prost(uint64, tag = "2")]
+    pub base_revision: u64,
+    /// the todo as stored on the server, unset if it was removed
+    #[prost(message, optional, tag = "3")]
+    pub current: ::core::option::Option<Todo>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SyncResponse {
+    #[prost(string, tag = "1")]
+    pub change_id: ::prost::alloc::string::String,
+    #[prost(oneof = "sync_response::Outcome", tags = "2, 3, 4, 5")]
+    pub outcome: ::core::option::Option<sync_response::Outcome>,
+}
+/// Nested message and enum types in `SyncResponse`.
+pub mod sync_response {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Outcome {
+        /// the todo as stored after the change
+        #[prost(message, tag = "2")]
+        Accepted(super::Todo),
+        #[prost(message, tag = "3")]
+        Removed(super::TodoIdentifier),
+        #[prost(message, tag = "4")]
+        Conflict(super::SyncConflict),
+        /// the change was invalid or not allowed
+        #[prost(message, tag = "5")]
+        Error(super::TodoChangeResult),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoListRequest {
+    /// only todos carrying this tag
+    #[prost(string, optional, tag = "1")]
+    pub tag: ::core::option::Option<::prost::alloc::string::String>,
+    /// only todos past their due date that are not completed
+    #[prost(bool, tag = "2")]
+    pub overdue: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TodoListResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub todos: ::prost::alloc::vec::Vec<Todo>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct TodoDescriptor {
     #[prost(string, optional, tag = "1")]
     pub description: ::core::option::Option<::prost::alloc::string::String>,
@@ -37,6 +165,18 @@ pub struct Todo {
     pub status: i32,
     #[prost(message, optional, tag = "3")]
     pub descriptor: ::core::option::Option<TodoDescriptor>,
+    #[prost(message, optional, tag = "4")]
+    pub due_at: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(enumeration = "TodoPriority", tag = "5")]
+    pub priority: i32,
+    #[prost(string, repeated, tag = "6")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// set on subtasks; a todo cannot be completed while it has open subtasks
+    #[prost(message, optional, tag = "7")]
+    pub parent_id: ::core::option::Option<TodoIdentifier>,
+    /// bumped by the server on every change to the todo, ignored when sent by clients
+    #[prost(uint64, tag = "8")]
+    pub revision: u64,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -67,6 +207,67 @@ impl TodoStatus {
         }
     }
 }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TodoPriority {
+    None = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+}
+impl TodoPriority {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            TodoPriority::None => "NONE",
+            TodoPriority::Low => "LOW",
+            TodoPriority::Medium => "MEDIUM",
+            TodoPriority::High => "HIGH",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "NONE" => Some(Self::None),
+            "LOW" => Some(Self::Low),
+            "MEDIUM" => Some(Self::Medium),
+            "HIGH" => Some(Self::High),
+            _ => None,
+        }
+    }
+}
+/// How Sync resolves a change made against an older revision of a todo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ConflictPolicy {
+    /// apply the change anyway, overwriting the newer server state
+    LastWriterWins = 0,
+    /// leave the todo as it is and report the conflict
+    Reject = 1,
+}
+impl ConflictPolicy {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::LastWriterWins => "LAST_WRITER_WINS",
+            ConflictPolicy::Reject => "REJECT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "LAST_WRITER_WINS" => Some(Self::LastWriterWins),
+            "REJECT" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod todos_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -237,6 +438,28 @@ pub mod todos_client {
             req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "Get"));
             self.inner.unary(req, path, codec).await
         }
+        pub async fn list(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TodoListRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoListResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/todos.Todos/List");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "List"));
+            self.inner.unary(req, path, codec).await
+        }
         pub async fn watch(
             &mut self,
             request: impl tonic::IntoRequest<super::TodoIdentifier>,
@@ -259,6 +482,116 @@ pub mod todos_client {
             req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "Watch"));
             self.inner.server_streaming(req, path, codec).await
         }
+        pub async fn update_todo(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TodoUpdateRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoChangeResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/todos.Todos/UpdateTodo");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "UpdateTodo"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn batch_add(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TodoBatchAddRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoBatchResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/todos.Todos/BatchAdd");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "BatchAdd"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn batch_update(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TodoBatchUpdateRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoBatchResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/todos.Todos/BatchUpdate");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "BatchUpdate"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn import(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::TodoChange>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoBatchResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/todos.Todos/Import");
+            let mut req = request.into_streaming_request();
+            req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "Import"));
+            self.inner.client_streaming(req, path, codec).await
+        }
+        pub async fn sync(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::SyncRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::SyncResponse>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/todos.Todos/Sync");
+            let mut req = request.into_streaming_request();
+            req.extensions_mut().insert(GrpcMethod::new("todos.Todos", "Sync"));
+            self.inner.streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -293,6 +626,13 @@ pub mod todos_server {
             &self,
             request: tonic::Request<super::TodoIdentifier>,
         ) -> std::result::Result<tonic::Response<super::Todo>, tonic::Status>;
+        async fn list(
+            &self,
+            request: tonic::Request<super::TodoListRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoListResponse>,
+            tonic::Status,
+        >;
         /// Server streaming response type for the Watch method.
         type WatchStream: tonic::codegen::tokio_stream::Stream<
                 Item = std::result::Result<super::Todo, tonic::Status>,
@@ -303,6 +643,44 @@ pub mod todos_server {
             &self,
             request: tonic::Request<super::TodoIdentifier>,
         ) -> std::result::Result<tonic::Response<Self::WatchStream>, tonic::Status>;
+        async fn update_todo(
+            &self,
+            request: tonic::Request<super::TodoUpdateRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoChangeResponse>,
+            tonic::Status,
+        >;
+        async fn batch_add(
+            &self,
+            request: tonic::Request<super::TodoBatchAddRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoBatchResponse>,
+            tonic::Status,
+        >;
+        async fn batch_update(
+            &self,
+            request: tonic::Request<super::TodoBatchUpdateRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoBatchResponse>,
+            tonic::Status,
+        >;
+        async fn import(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::TodoChange>>,
+        ) -> std::result::Result<
+            tonic::Response<super::TodoBatchResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the Sync method.
+        type SyncStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::SyncResponse, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        async fn sync(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::SyncRequest>>,
+        ) -> std::result::Result<tonic::Response<Self::SyncStream>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct TodosServer<T: Todos> {
@@ -561,6 +939,50 @@ pub mod todos_server {
                     };
                     Box::pin(fut)
                 }
+                "/todos.Todos/List" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListSvc<T: Todos>(pub Arc<T>);
+                    impl<T: Todos> tonic::server::UnaryService<super::TodoListRequest>
+                    for ListSvc<T> {
+                        type Response = super::TodoListResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TodoListRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Todos>::list(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/todos.Todos/Watch" => {
                     #[allow(non_camel_case_types)]
                     struct WatchSvc<T: Todos>(pub Arc<T>);
@@ -608,6 +1030,233 @@ pub mod todos_server {
                     };
                     Box::pin(fut)
                 }
+                "/todos.Todos/UpdateTodo" => {
+                    #[allow(non_camel_case_types)]
+                    struct UpdateTodoSvc<T: Todos>(pub Arc<T>);
+                    impl<T: Todos> tonic::server::UnaryService<super::TodoUpdateRequest>
+                    for UpdateTodoSvc<T> {
+                        type Response = super::TodoChangeResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TodoUpdateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Todos>::update_todo(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = UpdateTodoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/todos.Todos/BatchAdd" => {
+                    #[allow(non_camel_case_types)]
+                    struct BatchAddSvc<T: Todos>(pub Arc<T>);
+                    impl<
+                        T: Todos,
+                    > tonic::server::UnaryService<super::TodoBatchAddRequest>
+                    for BatchAddSvc<T> {
+                        type Response = super::TodoBatchResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TodoBatchAddRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Todos>::batch_add(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = BatchAddSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/todos.Todos/BatchUpdate" => {
+                    #[allow(non_camel_case_types)]
+                    struct BatchUpdateSvc<T: Todos>(pub Arc<T>);
+                    impl<
+                        T: Todos,
+                    > tonic::server::UnaryService<super::TodoBatchUpdateRequest>
+                    for BatchUpdateSvc<T> {
+                        type Response = super::TodoBatchResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TodoBatchUpdateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Todos>::batch_update(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = BatchUpdateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/todos.Todos/Import" => {
+                    #[allow(non_camel_case_types)]
+                    struct ImportSvc<T: Todos>(pub Arc<T>);
+                    impl<
+                        T: Todos,
+                    > tonic::server::ClientStreamingService<super::TodoChange>
+                    for ImportSvc<T> {
+                        type Response = super::TodoBatchResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<tonic::Streaming<super::TodoChange>>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Todos>::import(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ImportSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.client_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/todos.Todos/Sync" => {
+                    #[allow(non_camel_case_types)]
+                    struct SyncSvc<T: Todos>(pub Arc<T>);
+                    impl<T: Todos> tonic::server::StreamingService<super::SyncRequest>
+                    for SyncSvc<T> {
+                        type Response = super::SyncResponse;
+                        type ResponseStream = T::SyncStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<tonic::Streaming<super::SyncRequest>>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Todos>::sync(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SyncSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -0,0 +1,296 @@
+//! Validation of incoming requests.
+//!
+//! Malformed messages are rejected with `INVALID_ARGUMENT` and a `google.rpc.BadRequest`
+//! detail listing every offending field, so clients can render per-field errors.
+//! Requests that are well formed but not allowed for the current state of a todo
+//! are rejected with `FAILED_PRECONDITION` and a `google.rpc.PreconditionFailure` detail.
+
+#![allow(clippy::result_large_err)]
+
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, PreconditionViolation, StatusExt};
+
+use crate::{Todo, TodoPriority, TodoStatus, TodoStatusUpdateRequest, TodoUpdateRequest};
+
+/// Field paths accepted in the update mask of `UpdateTodo`.
+pub const UPDATABLE_PATHS: &[&str] = &[
+    "status",
+    "descriptor",
+    "descriptor.title",
+    "descriptor.description",
+    "due_at",
+    "priority",
+    "tags",
+    "parent_id",
+];
+
+pub fn validate_todo(todo: &Todo) -> Result<(), Status> {
+    let mut details = ErrorDetails::new();
+
+    if todo.id.is_none() {
+        details.add_bad_request_violation("id", "id is required");
+    }
+    check_status(&mut details, "status", todo.status);
+    match &todo.descriptor {
+        Some(descriptor) if descriptor.title.trim().is_empty() => {
+            details.add_bad_request_violation("descriptor.title", "title must not be empty");
+        }
+        Some(_) => {}
+        None => {
+            details.add_bad_request_violation("descriptor", "descriptor is required");
+        }
+    }
+    check_priority(&mut details, "priority", todo.priority);
+    check_tags(&mut details, "tags", &todo.tags);
+    check_parent(&mut details, "parent_id", todo);
+
+    into_result(details)
+}
+
+pub fn validate_status_update(request: &TodoStatusUpdateRequest) -> Result<(), Status> {
+    let mut details = ErrorDetails::new();
+
+    if request.id.is_none() {
+        details.add_bad_request_violation("id", "id is required");
+    }
+    check_status(&mut details, "status", request.status);
+
+    into_result(details)
+}
+
+/// Validates an `UpdateTodo` request.
+///
+/// Stored todos always have a title, so the patched title only has to be checked
+/// when the mask touches it.
+pub fn validate_todo_update(request: &TodoUpdateRequest) -> Result<(), Status> {
+    let mut details = ErrorDetails::new();
+
+    let paths = request
+        .update_mask
+        .as_ref()
+        .map(|mask| mask.paths.as_slice())
+        .unwrap_or_default();
+
+    for (index, path) in paths.iter().enumerate() {
+        let field = format!("update_mask.paths[{}]", index);
+        if path == "id" {
+            details.add_bad_request_violation(field, "id cannot be updated");
+        } else if !UPDATABLE_PATHS.contains(&path.as_str()) {
+            details.add_bad_request_violation(field, format!("unknown field path: {}", path));
+        }
+    }
+
+    let todo = match &request.todo {
+        Some(todo) => todo,
+        None => {
+            details.add_bad_request_violation("todo", "todo is required");
+            return into_result(details);
+        }
+    };
+
+    if todo.id.is_none() {
+        details.add_bad_request_violation("todo.id", "id is required");
+    }
+
+    let touches = |field: &str| paths.is_empty() || paths.iter().any(|path| path == field);
+
+    if touches("status") {
+        check_status(&mut details, "todo.status", todo.status);
+    }
+
+    if touches("descriptor") || touches("descriptor.title") {
+        match &todo.descriptor {
+            Some(descriptor) if descriptor.title.trim().is_empty() => {
+                details
+                    .add_bad_request_violation("todo.descriptor.title", "title must not be empty");
+            }
+            Some(_) => {}
+            None => {
+                details.add_bad_request_violation("todo.descriptor", "descriptor is required");
+            }
+        }
+    }
+
+    if touches("priority") {
+        check_priority(&mut details, "todo.priority", todo.priority);
+    }
+
+    if touches("tags") {
+        check_tags(&mut details, "todo.tags", &todo.tags);
+    }
+
+    if touches("parent_id") {
+        check_parent(&mut details, "todo.parent_id", todo);
+    }
+
+    into_result(details)
+}
+
+/// Checks that a todo may move from status `from` to status `to`.
+///
+/// A completed todo can be reopened as ongoing, but not reset to new.
+pub fn check_transition(id: u32, from: i32, to: i32) -> Result<(), Status> {
+    if from == TodoStatus::Completed as i32 && to == TodoStatus::New as i32 {
+        return Err(precondition_failed(
+            "invalid status transition",
+            vec![PreconditionViolation::new(
+                "STATUS_TRANSITION",
+                format!("todos/{}", id),
+                "a completed todo cannot be moved back to NEW",
+            )],
+        ));
+    }
+
+    Ok(())
+}
+
+/// Builds a `FAILED_PRECONDITION` status carrying `violations`.
+pub fn precondition_failed(message: &str, violations: Vec<PreconditionViolation>) -> Status {
+    Status::with_error_details(
+        Code::FailedPrecondition,
+        message,
+        ErrorDetails::with_precondition_failure(violations),
+    )
+}
+
+fn check_status(details: &mut ErrorDetails, field: &str, status: i32) {
+    if TodoStatus::try_from(status).is_err() {
+        details.add_bad_request_violation(field, format!("unknown status: {}", status));
+    }
+}
+
+fn check_priority(details: &mut ErrorDetails, field: &str, priority: i32) {
+    if TodoPriority::try_from(priority).is_err() {
+        details.add_bad_request_violation(field, format!("unknown priority: {}", priority));
+    }
+}
+
+fn check_tags(details: &mut ErrorDetails, field: &str, tags: &[String]) {
+    for (index, tag) in tags.iter().enumerate() {
+        if tag.trim().is_empty() {
+            details.add_bad_request_violation(
+                format!("{}[{}]", field, index),
+                "tag must not be empty",
+            );
+        }
+    }
+}
+
+fn check_parent(details: &mut ErrorDetails, field: &str, todo: &Todo) {
+    if todo.parent_id.is_some() && todo.parent_id == todo.id {
+        details.add_bad_request_violation(field, "a todo cannot be its own parent");
+    }
+}
+
+fn into_result(details: ErrorDetails) -> Result<(), Status> {
+    if details.has_bad_request_violations() {
+        return Err(Status::with_error_details(
+            Code::InvalidArgument,
+            "request has invalid fields",
+            details,
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TodoDescriptor, TodoIdentifier};
+
+    fn violated_fields(status: Status) -> Vec<String> {
+        assert_eq!(status.code(), Code::InvalidArgument);
+        status
+            .get_details_bad_request()
+            .expect("bad request details")
+            .field_violations
+            .into_iter()
+            .map(|violation| violation.field)
+            .collect()
+    }
+
+    #[test]
+    fn todo_reports_every_invalid_field() {
+        let todo = Todo {
+            id: None,
+            status: 42,
+            descriptor: Some(TodoDescriptor {
+                title: " ".into(),
+                description: None,
+            }),
+            priority: 7,
+            tags: vec!["work".into(), "".into()],
+            ..Default::default()
+        };
+
+        let fields = violated_fields(validate_todo(&todo).unwrap_err());
+        assert_eq!(
+            fields,
+            ["id", "status", "descriptor.title", "priority", "tags[1]"]
+        );
+    }
+
+    #[test]
+    fn todo_update_rejects_id_and_unknown_paths() {
+        let request = TodoUpdateRequest {
+            todo: Some(Todo {
+                id: Some(TodoIdentifier { id: 1 }),
+                ..Default::default()
+            }),
+            update_mask: Some(prost_types::FieldMask {
+                paths: vec!["status".into(), "id".into(), "owner".into()],
+            }),
+        };
+
+        let fields = violated_fields(validate_todo_update(&request).unwrap_err());
+        assert_eq!(fields, ["update_mask.paths[1]", "update_mask.paths[2]"]);
+    }
+
+    #[test]
+    fn todo_update_checks_title_only_when_masked() {
+        let mut request = TodoUpdateRequest {
+            todo: Some(Todo {
+                id: Some(TodoIdentifier { id: 1 }),
+                status: TodoStatus::Ongoing.into(),
+                descriptor: None,
+                ..Default::default()
+            }),
+            update_mask: Some(prost_types::FieldMask {
+                paths: vec!["status".into()],
+            }),
+        };
+        assert!(validate_todo_update(&request).is_ok());
+
+        request.update_mask = None;
+        let fields = violated_fields(validate_todo_update(&request).unwrap_err());
+        assert_eq!(fields, ["todo.descriptor"]);
+    }
+
+    #[test]
+    fn completed_todo_cannot_go_back_to_new() {
+        let completed = TodoStatus::Completed as i32;
+
+        let status = check_transition(1, completed, TodoStatus::New as i32).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert!(status.get_details_precondition_failure().is_some());
+
+        assert!(check_transition(1, completed, TodoStatus::Ongoing as i32).is_ok());
+    }
+
+    #[test]
+    fn todo_cannot_be_its_own_parent() {
+        let todo = Todo {
+            id: Some(TodoIdentifier { id: 1 }),
+            descriptor: Some(TodoDescriptor {
+                title: "write docs".into(),
+                description: None,
+            }),
+            parent_id: Some(TodoIdentifier { id: 1 }),
+            ..Default::default()
+        };
+
+        let fields = violated_fields(validate_todo(&todo).unwrap_err());
+        assert_eq!(fields, ["parent_id"]);
+    }
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::{spawn_authenticated_server, todo, with_token};
+use grpc_todos::{auth::Authenticator, TodoIdentifier, TodoListRequest};
+use tonic::Code;
+
+fn authenticator() -> Authenticator {
+    Authenticator::new([("alice-token", "alice"), ("bob-token", "bob")])
+}
+
+#[tokio::test]
+async fn requests_without_valid_token_are_unauthenticated() -> anyhow::Result<()> {
+    let mut client = spawn_authenticated_server(authenticator()).await?;
+
+    let status = client.list(TodoListRequest::default()).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    let status = client
+        .list(with_token(TodoListRequest::default(), "mallory-token"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn users_only_see_their_own_todos() -> anyhow::Result<()> {
+    let mut client = spawn_authenticated_server(authenticator()).await?;
+
+    client
+        .add(with_token(todo(1, "alice's todo"), "alice-token"))
+        .await?;
+    client
+        .add(with_token(todo(2, "bob's todo"), "bob-token"))
+        .await?;
+
+    let todos = client
+        .list(with_token(TodoListRequest::default(), "alice-token"))
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(todos, [todo(1, "alice's todo")]);
+
+    let todos = client
+        .list(with_token(TodoListRequest::default(), "bob-token"))
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(todos, [todo(2, "bob's todo")]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn touching_another_users_todo_is_permission_denied() -> anyhow::Result<()> {
+    let mut client = spawn_authenticated_server(authenticator()).await?;
+
+    client
+        .add(with_token(todo(1, "alice's todo"), "alice-token"))
+        .await?;
+
+    let status = client
+        .get(with_token(TodoIdentifier { id: 1 }, "bob-token"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = client
+        .remove(with_token(TodoIdentifier { id: 1 }, "bob-token"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = client
+        .add(with_token(todo(1, "bob's todo"), "bob-token"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let status = client
+        .get(with_token(TodoIdentifier { id: 2 }, "bob-token"))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use common::{spawn_authenticated_server, spawn_server, todo, with_token};
+use grpc_todos::{
+    auth::Authenticator, todo_change::Change, TodoBatchAddRequest, TodoBatchUpdateRequest,
+    TodoChange, TodoIdentifier, TodoListRequest, TodoStatus, TodoUpdateRequest,
+};
+use tonic::Code;
+
+fn codes(results: &[grpc_todos::TodoChangeResult]) -> Vec<Code> {
+    results
+        .iter()
+        .map(|result| Code::from_i32(result.code))
+        .collect()
+}
+
+fn complete(id: u32) -> TodoUpdateRequest {
+    let mut patch = todo(id, "");
+    patch.status = TodoStatus::Completed.into();
+    TodoUpdateRequest {
+        todo: Some(patch),
+        update_mask: Some(prost_types::FieldMask {
+            paths: vec!["status".into()],
+        }),
+    }
+}
+
+#[tokio::test]
+async fn batch_add_applies_every_todo() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+
+    let response = client
+        .batch_add(TodoBatchAddRequest {
+            todos: vec![todo(1, "a"), todo(2, "b")],
+        })
+        .await?
+        .into_inner();
+    assert!(response.applied);
+    assert_eq!(codes(&response.results), [Code::Ok, Code::Ok]);
+
+    let todos = client
+        .list(TodoListRequest::default())
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(todos, [todo(1, "a"), todo(2, "b")]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failing_batch_applies_nothing() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    client.add(todo(1, "a")).await?;
+
+    let response = client
+        .batch_add(TodoBatchAddRequest {
+            todos: vec![todo(2, "b"), todo(1, "again"), todo(3, "")],
+        })
+        .await?
+        .into_inner();
+    assert!(!response.applied);
+    assert_eq!(
+        codes(&response.results),
+        [Code::Aborted, Code::AlreadyExists, Code::InvalidArgument]
+    );
+    assert_eq!(response.results[1].id, Some(TodoIdentifier { id: 1 }));
+
+    let response = client
+        .batch_update(TodoBatchUpdateRequest {
+            updates: vec![complete(1), complete(2)],
+        })
+        .await?
+        .into_inner();
+    assert!(!response.applied);
+    assert_eq!(codes(&response.results), [Code::Aborted, Code::NotFound]);
+
+    let todos = client
+        .list(TodoListRequest::default())
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(todos, [todo(1, "a")]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn import_streams_mixed_changes() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    client.add(todo(1, "a")).await?;
+
+    let changes = vec![
+        Change::Add(todo(2, "b")),
+        Change::Update(complete(2)),
+        Change::Remove(TodoIdentifier { id: 1 }),
+    ];
+    let stream = tokio_stream::iter(changes.into_iter().map(|change| TodoChange {
+        change: Some(change),
+    }));
+
+    let response = client.import(stream).await?.into_inner();
+    assert!(response.applied);
+    assert_eq!(codes(&response.results), [Code::Ok, Code::Ok, Code::Ok]);
+
+    let mut expected = todo(2, "b");
+    expected.status = TodoStatus::Completed.into();
+    expected.revision = 1;
+    let todos = client
+        .list(TodoListRequest::default())
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(todos, [expected]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rolling_back_a_batch_leaves_other_users_alone() -> anyhow::Result<()> {
+    let auth = Authenticator::new([("alice-token", "alice"), ("bob-token", "bob")]);
+    let mut client = spawn_authenticated_server(auth).await?;
+    client
+        .add(with_token(todo(1, "alice's"), "alice-token"))
+        .await?;
+    client
+        .add(with_token(todo(2, "bob's"), "bob-token"))
+        .await?;
+
+    // bob's batch touches alice's todo after changing his own, and is rolled back
+    let response = client
+        .batch_update(with_token(
+            TodoBatchUpdateRequest {
+                updates: vec![complete(2), complete(1)],
+            },
+            "bob-token",
+        ))
+        .await?
+        .into_inner();
+    assert!(!response.applied);
+    assert_eq!(
+        codes(&response.results),
+        [Code::Aborted, Code::PermissionDenied]
+    );
+
+    let list = |token| with_token(TodoListRequest::default(), token);
+    let bobs = client.list(list("bob-token")).await?.into_inner().todos;
+    assert_eq!(bobs, [todo(2, "bob's")]);
+    let alices = client.list(list("alice-token")).await?.into_inner().todos;
+    assert_eq!(alices, [todo(1, "alice's")]);
+
+    Ok(())
+}
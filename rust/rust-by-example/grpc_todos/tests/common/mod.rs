@@ -0,0 +1,122 @@
+//! Shared harness for the integration tests: runs a `TodoService` in-process on an
+//! ephemeral port and connects the generated client to it.
+
+// each test binary only uses part of the harness
+#![allow(dead_code)]
+
+pub mod pki;
+
+use std::{net::SocketAddr, time::Duration};
+
+use grpc_todos::{
+    auth::Authenticator, server::TodoService, telemetry::Metrics, tls, todos_client::TodosClient,
+    todos_server::TodosServer, Todo, TodoDescriptor, TodoIdentifier, TodoStatus,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    transport::{server::Router, Channel, ClientTlsConfig, Server},
+    Request,
+};
+use tower::Layer;
+
+pub async fn spawn_server() -> anyhow::Result<TodosClient<Channel>> {
+    spawn_server_with(TodoService::default()).await
+}
+
+/// Like [`spawn_server`], but serves the given service so the test can keep a clone of it.
+pub async fn spawn_server_with(service: TodoService) -> anyhow::Result<TodosClient<Channel>> {
+    serve(Server::builder().add_service(TodosServer::new(service))).await
+}
+
+/// Like [`spawn_server`], but requires the bearer tokens known to `auth`.
+pub async fn spawn_authenticated_server(
+    auth: Authenticator,
+) -> anyhow::Result<TodosClient<Channel>> {
+    let service = TodosServer::with_interceptor(TodoService::default(), auth);
+    serve(Server::builder().add_service(service)).await
+}
+
+/// Like [`spawn_server`], but records every call in `metrics`.
+pub async fn spawn_instrumented_server(metrics: &Metrics) -> anyhow::Result<TodosClient<Channel>> {
+    let service = metrics
+        .layer()
+        .layer(TodosServer::new(TodoService::default()));
+    serve(Server::builder().add_service(service)).await
+}
+
+/// Serves a fresh `TodoService` over TLS and returns its address, so the test can
+/// connect with the client settings under test.
+pub async fn spawn_tls_server(
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+) -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let router = Server::builder().add_service(TodosServer::new(TodoService::default()));
+    tokio::spawn(async move {
+        router
+            .serve_with_incoming(tls::incoming(listener, acceptor, handshake_timeout))
+            .await
+            .unwrap();
+    });
+
+    Ok(addr)
+}
+
+/// Connects to a server started by [`spawn_tls_server`].
+pub async fn connect_tls(
+    addr: SocketAddr,
+    tls: ClientTlsConfig,
+) -> anyhow::Result<TodosClient<Channel>> {
+    let channel = Channel::from_shared(format!("https://localhost:{}", addr.port()))?
+        .tls_config(tls)?
+        .connect()
+        .await?;
+    Ok(TodosClient::new(channel))
+}
+
+async fn serve(router: Router) -> anyhow::Result<TodosClient<Channel>> {
+    let addr = listen(router).await?;
+    let client = TodosClient::connect(format!("http://{}", addr)).await?;
+    Ok(client)
+}
+
+/// Serves `router` on an ephemeral port and returns its address.
+pub async fn listen(router: Router) -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        router
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    Ok(addr)
+}
+
+pub fn todo(id: u32, title: &str) -> Todo {
+    Todo {
+        id: Some(TodoIdentifier { id }),
+        status: TodoStatus::New.into(),
+        descriptor: Some(TodoDescriptor {
+            title: title.into(),
+            description: None,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Wraps `message` in a request carrying `token` as bearer token.
+pub fn with_token<T>(message: T, token: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert(
+        "authorization",
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    request
+}
@@ -0,0 +1,110 @@
+//! Throwaway certificate authority for the TLS tests, generated on every run so the tests
+//! need neither network access nor checked-in keys.
+
+use std::{fs, path::PathBuf};
+
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+};
+use tokio_rustls::rustls;
+use tonic::transport::{self, ClientTlsConfig, Identity};
+
+pub struct Pki {
+    ca: Certificate,
+    dir: PathBuf,
+}
+
+/// A certificate and its private key, both PEM encoded.
+pub struct Issued {
+    pub cert: String,
+    pub key: String,
+}
+
+impl Pki {
+    /// Creates a CA whose files are written under a directory named after `name`.
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, format!("{} test CA", name));
+
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+            .join("pki")
+            .join(name);
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            ca: Certificate::from_params(params)?,
+            dir,
+        })
+    }
+
+    pub fn ca_pem(&self) -> String {
+        self.ca.serialize_pem().expect("CA is self-signed")
+    }
+
+    /// Issues a certificate for the `localhost` server.
+    pub fn server(&self) -> anyhow::Result<Issued> {
+        self.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth)
+    }
+
+    pub fn client(&self, name: &str) -> anyhow::Result<Issued> {
+        self.issue(name, ExtendedKeyUsagePurpose::ClientAuth)
+    }
+
+    /// Writes `contents` to `file` in this CA's directory and returns its path.
+    pub fn write(&self, file: &str, contents: &str) -> anyhow::Result<PathBuf> {
+        let path = self.dir.join(file);
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// Client settings trusting this CA, optionally presenting `identity`.
+    pub fn client_config(&self, identity: Option<&Issued>) -> ClientTlsConfig {
+        let config = ClientTlsConfig::new()
+            .domain_name("localhost")
+            .ca_certificate(transport::Certificate::from_pem(self.ca_pem()));
+        match identity {
+            Some(issued) => config.identity(Identity::from_pem(&issued.cert, &issued.key)),
+            None => config,
+        }
+    }
+
+    /// Like [`Pki::client_config`], for clients talking to the REST gateway directly.
+    pub fn rustls_client_config(
+        &self,
+        identity: Option<&Issued>,
+    ) -> anyhow::Result<rustls::ClientConfig> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&rustls::Certificate(self.ca.serialize_der()?))?;
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+
+        let config = match identity {
+            Some(issued) => {
+                let certs = rustls_pemfile::certs(&mut issued.cert.as_bytes())?;
+                let key = rustls_pemfile::pkcs8_private_keys(&mut issued.key.as_bytes())?;
+                builder.with_client_auth_cert(
+                    certs.into_iter().map(rustls::Certificate).collect(),
+                    rustls::PrivateKey(key.into_iter().next().expect("key is PKCS#8")),
+                )?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(config)
+    }
+
+    fn issue(&self, name: &str, usage: ExtendedKeyUsagePurpose) -> anyhow::Result<Issued> {
+        let mut params = CertificateParams::new(vec![name.to_string()]);
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.extended_key_usages = vec![usage];
+
+        let cert = Certificate::from_params(params)?;
+        Ok(Issued {
+            cert: cert.serialize_pem_with_signer(&self.ca)?,
+            key: cert.serialize_private_key_pem(),
+        })
+    }
+}
@@ -0,0 +1,239 @@
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use grpc_todos::{
+    auth::Authenticator,
+    gateway::{self, ChangeJson, TodoJson},
+    server::TodoService,
+    todos_server::TodosServer,
+    TodoListRequest, TodoListResponse,
+};
+use prost::Message;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+async fn call(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, body)
+}
+
+fn router() -> Router {
+    gateway::router(TodoService::default(), Authenticator::disabled())
+}
+
+#[tokio::test]
+async fn crud_over_json() {
+    let router = router();
+
+    let (status, body) = call(
+        &router,
+        Method::POST,
+        "/todos",
+        None,
+        Some(json!({ "id": 1, "title": "write docs" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let change: ChangeJson = serde_json::from_value(body).unwrap();
+    assert_eq!(change.id, Some(1));
+
+    let (status, body) = call(
+        &router,
+        Method::PATCH,
+        "/todos/1",
+        None,
+        Some(json!({ "status": "ONGOING", "description": "for the gateway" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, body) = call(&router, Method::GET, "/todos/1", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let todo: TodoJson = serde_json::from_value(body).unwrap();
+    assert_eq!(
+        todo,
+        TodoJson {
+            id: 1,
+            status: "ONGOING".into(),
+            title: "write docs".into(),
+            description: Some("for the gateway".into()),
+            due_at: None,
+            priority: "NONE".into(),
+            tags: vec![],
+            parent_id: None,
+            revision: 1,
+        }
+    );
+
+    let (status, body) = call(&router, Method::GET, "/todos", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().map(Vec::len), Some(1));
+
+    let (status, _) = call(&router, Method::DELETE, "/todos/1", None, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = call(&router, Method::GET, "/todos/1", None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "NotFound");
+}
+
+#[tokio::test]
+async fn patching_optional_fields_with_null_clears_them() {
+    let router = router();
+
+    for todo in [
+        json!({ "id": 1, "title": "parent" }),
+        json!({
+            "id": 2,
+            "title": "child",
+            "description": "of the parent",
+            "due_at": "2030-01-01T00:00:00Z",
+            "parent_id": 1,
+        }),
+    ] {
+        let (status, body) = call(&router, Method::POST, "/todos", None, Some(todo)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+    }
+
+    let (status, body) = call(
+        &router,
+        Method::PATCH,
+        "/todos/2",
+        None,
+        Some(json!({ "description": null, "due_at": null, "parent_id": null })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (_, body) = call(&router, Method::GET, "/todos/2", None, None).await;
+    let todo: TodoJson = serde_json::from_value(body).unwrap();
+    assert_eq!(todo.title, "child");
+    assert_eq!(
+        (todo.description, todo.due_at, todo.parent_id),
+        (None, None, None)
+    );
+}
+
+#[tokio::test]
+async fn grpc_web_calls_reach_the_same_service() {
+    let service = TodoService::default();
+    let (status, _) = call(
+        &gateway::router(service.clone(), Authenticator::disabled()),
+        Method::POST,
+        "/todos",
+        None,
+        Some(json!({ "id": 1, "title": "added over json" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // a message is framed by a flag byte and its length
+    let message = TodoListRequest::default().encode_to_vec();
+    let mut frame = vec![0];
+    frame.extend((message.len() as u32).to_be_bytes());
+    frame.extend(message);
+    let request = Request::post("/todos.Todos/List")
+        .header(header::CONTENT_TYPE, "application/grpc-web+proto")
+        .body(Body::from(frame))
+        .unwrap();
+
+    let response = tonic_web::enable(TodosServer::new(service))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/grpc-web+proto"
+    );
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let (flag, len) = (
+        body[0],
+        u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize,
+    );
+    assert_eq!(flag, 0);
+    let todos = TodoListResponse::decode(&body[5..5 + len]).unwrap().todos;
+    assert_eq!(todos.len(), 1);
+    assert_eq!(
+        todos[0].descriptor.as_ref().unwrap().title,
+        "added over json"
+    );
+
+    // followed by the trailers, flagged by the high bit
+    let trailers = &body[5 + len..];
+    assert_eq!(trailers[0], 0x80);
+    assert!(String::from_utf8_lossy(&trailers[5..]).contains("grpc-status:0"));
+}
+
+#[tokio::test]
+async fn invalid_fields_map_to_bad_request_with_violations() {
+    let router = router();
+
+    let (status, body) = call(
+        &router,
+        Method::POST,
+        "/todos",
+        None,
+        Some(json!({ "id": 1, "title": "" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["field_violations"][0]["field"], "descriptor.title");
+
+    let (status, body) = call(
+        &router,
+        Method::POST,
+        "/todos",
+        None,
+        Some(json!({ "id": 1, "title": "write docs", "status": "DONE" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["field_violations"][0]["field"], "status");
+}
+
+#[tokio::test]
+async fn gateway_uses_the_interceptor() {
+    let auth = Authenticator::new([("alice-token", "alice"), ("bob-token", "bob")]);
+    let router = gateway::router(TodoService::default(), auth);
+
+    let (status, _) = call(&router, Method::GET, "/todos", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(
+        &router,
+        Method::POST,
+        "/todos",
+        Some("alice-token"),
+        Some(json!({ "id": 1, "title": "alice's todo" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = call(&router, Method::GET, "/todos/1", Some("bob-token"), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
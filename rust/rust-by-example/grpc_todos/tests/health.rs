@@ -0,0 +1,53 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::listen;
+use grpc_todos::{server::TodoService, shutdown};
+use tokio::sync::watch;
+use tonic::transport::{Channel, Server};
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+async fn status(client: &mut HealthClient<Channel>) -> anyhow::Result<ServingStatus> {
+    let request = HealthCheckRequest {
+        service: "todos.Todos".into(),
+    };
+    let response = client.check(request).await?.into_inner();
+    Ok(response.status())
+}
+
+#[tokio::test]
+async fn reports_not_serving_for_the_grace_period_before_stopping() -> anyhow::Result<()> {
+    let (mut reporter, health) = tonic_health::server::health_reporter();
+    shutdown::report_serving(&mut reporter).await;
+
+    let addr = listen(Server::builder().add_service(health)).await?;
+    let channel = Channel::from_shared(format!("http://{}", addr))?
+        .connect()
+        .await?;
+    let mut client = HealthClient::new(channel);
+    assert_eq!(status(&mut client).await?, ServingStatus::Serving);
+
+    let grace = Duration::from_millis(300);
+    let (stop_tx, mut stop_rx) = watch::channel(false);
+    let start = Instant::now();
+    tokio::spawn(shutdown::drain(
+        reporter,
+        TodoService::default(),
+        grace,
+        stop_tx,
+    ));
+
+    // the health service is still answering, and says NOT_SERVING, before the stop
+    while status(&mut client).await? != ServingStatus::NotServing {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(!*stop_rx.borrow());
+
+    stop_rx.wait_for(|stop| *stop).await?;
+    assert!(start.elapsed() >= grace);
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::{spawn_server, todo};
+use grpc_todos::{
+    sync_response::Outcome, todo_change::Change, todos_client::TodosClient, ConflictPolicy,
+    SyncRequest, Todo, TodoChange, TodoIdentifier, TodoStatus, TodoUpdateRequest,
+};
+use tonic::{transport::Channel, Code};
+
+fn request(
+    change_id: &str,
+    change: Change,
+    base_revision: u64,
+    policy: ConflictPolicy,
+) -> SyncRequest {
+    SyncRequest {
+        change_id: change_id.into(),
+        change: Some(TodoChange {
+            change: Some(change),
+        }),
+        base_revision,
+        policy: policy.into(),
+    }
+}
+
+fn rename(id: u32, title: &str) -> Change {
+    Change::Update(TodoUpdateRequest {
+        todo: Some(todo(id, title)),
+        update_mask: Some(prost_types::FieldMask {
+            paths: vec!["descriptor.title".into()],
+        }),
+    })
+}
+
+/// Streams `requests` to `Sync` and collects the outcomes by change id.
+async fn sync(
+    client: &mut TodosClient<Channel>,
+    requests: Vec<SyncRequest>,
+) -> anyhow::Result<Vec<(String, Outcome)>> {
+    let mut responses = client
+        .sync(tokio_stream::iter(requests))
+        .await?
+        .into_inner();
+
+    let mut outcomes = Vec::new();
+    while let Some(response) = responses.message().await? {
+        outcomes.push((response.change_id, response.outcome.unwrap()));
+    }
+    Ok(outcomes)
+}
+
+fn with_revision(mut todo: Todo, revision: u64) -> Todo {
+    todo.revision = revision;
+    todo
+}
+
+#[tokio::test]
+async fn accepted_changes_come_back_with_their_revision() -> anyhow::Result<()> {
+    use ConflictPolicy::Reject;
+    let mut client = spawn_server().await?;
+
+    let outcomes = sync(
+        &mut client,
+        vec![
+            request("a", Change::Add(todo(1, "offline")), 0, Reject),
+            request("b", rename(1, "renamed"), 0, Reject),
+            request("c", Change::Remove(TodoIdentifier { id: 1 }), 1, Reject),
+        ],
+    )
+    .await?;
+
+    assert_eq!(
+        outcomes,
+        [
+            ("a".into(), Outcome::Accepted(todo(1, "offline"))),
+            (
+                "b".into(),
+                Outcome::Accepted(with_revision(todo(1, "renamed"), 1))
+            ),
+            ("c".into(), Outcome::Removed(TodoIdentifier { id: 1 })),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_changes_conflict_or_overwrite_depending_on_policy() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    client.add(todo(1, "original")).await?;
+    client
+        .update(grpc_todos::TodoStatusUpdateRequest {
+            id: Some(TodoIdentifier { id: 1 }),
+            status: TodoStatus::Ongoing.into(),
+        })
+        .await?;
+
+    let mut current = with_revision(todo(1, "original"), 1);
+    current.status = TodoStatus::Ongoing.into();
+
+    let outcomes = sync(
+        &mut client,
+        vec![
+            request("reject", rename(1, "mine"), 0, ConflictPolicy::Reject),
+            request("lww", rename(1, "mine"), 0, ConflictPolicy::LastWriterWins),
+        ],
+    )
+    .await?;
+
+    let Outcome::Conflict(conflict) = &outcomes[0].1 else {
+        panic!("expected a conflict, got {:?}", outcomes[0]);
+    };
+    assert_eq!(conflict.base_revision, 0);
+    assert_eq!(conflict.current.as_ref(), Some(&current));
+
+    let mut overwritten = with_revision(todo(1, "mine"), 2);
+    overwritten.status = TodoStatus::Ongoing.into();
+    assert_eq!(outcomes[1].1, Outcome::Accepted(overwritten));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn removed_todos_and_invalid_changes_do_not_end_the_stream() -> anyhow::Result<()> {
+    use ConflictPolicy::LastWriterWins;
+    let mut client = spawn_server().await?;
+
+    let outcomes = sync(
+        &mut client,
+        vec![
+            request("gone", rename(7, "edited offline"), 3, LastWriterWins),
+            request("invalid", Change::Add(todo(2, "")), 0, LastWriterWins),
+            request(
+                "twice",
+                Change::Remove(TodoIdentifier { id: 7 }),
+                3,
+                LastWriterWins,
+            ),
+            request("ok", Change::Add(todo(2, "valid")), 0, LastWriterWins),
+        ],
+    )
+    .await?;
+    assert_eq!(outcomes.len(), 4);
+
+    let Outcome::Conflict(conflict) = &outcomes[0].1 else {
+        panic!("expected a conflict, got {:?}", outcomes[0]);
+    };
+    assert_eq!(conflict.current, None);
+
+    let Outcome::Error(error) = &outcomes[1].1 else {
+        panic!("expected an error, got {:?}", outcomes[1]);
+    };
+    assert_eq!(Code::from_i32(error.code), Code::InvalidArgument);
+
+    assert_eq!(outcomes[2].1, Outcome::Removed(TodoIdentifier { id: 7 }));
+    assert_eq!(outcomes[3].1, Outcome::Accepted(todo(2, "valid")));
+
+    Ok(())
+}
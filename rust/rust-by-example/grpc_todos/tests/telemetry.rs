@@ -0,0 +1,133 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{uri::PathAndQuery, Request},
+};
+use common::{listen, spawn_instrumented_server, todo};
+use grpc_todos::{
+    server::TodoService,
+    telemetry::{metrics_router, Metrics},
+    todos_server::TodosServer,
+    TodoIdentifier,
+};
+use tonic::{
+    client::Grpc,
+    codec::ProstCodec,
+    transport::{Channel, Server},
+    Code,
+};
+use tower::{Layer, ServiceExt};
+
+async fn scrape(metrics: &Metrics) -> String {
+    let request = Request::get("/metrics").body(Body::empty()).unwrap();
+    let response = metrics_router(metrics.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+/// Waits for the `todos_active_watch_streams` gauge to reach `expected`, streams are only
+/// counted once the server has noticed them open or close.
+async fn wait_for_watches(metrics: &Metrics, expected: i64) {
+    let line = format!("todos_active_watch_streams {}", expected);
+    for _ in 0..50 {
+        if scrape(metrics).await.lines().any(|l| l == line) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!(
+        "gauge never reached {}:\n{}",
+        expected,
+        scrape(metrics).await
+    );
+}
+
+#[tokio::test]
+async fn calls_are_counted_by_method_and_code() -> anyhow::Result<()> {
+    let metrics = Metrics::default();
+    let mut client = spawn_instrumented_server(&metrics).await?;
+
+    client.add(todo(1, "measure me")).await?;
+    client.get(TodoIdentifier { id: 1 }).await?;
+    client.get(TodoIdentifier { id: 2 }).await.unwrap_err();
+
+    let scraped = scrape(&metrics).await;
+    for line in [
+        r#"grpc_server_requests_total{code="Ok",method="/todos.Todos/Add"} 1"#,
+        r#"grpc_server_requests_total{code="Ok",method="/todos.Todos/Get"} 1"#,
+        r#"grpc_server_requests_total{code="NotFound",method="/todos.Todos/Get"} 1"#,
+        r#"grpc_server_request_duration_seconds_count{method="/todos.Todos/Get"} 2"#,
+    ] {
+        assert!(
+            scraped.lines().any(|l| l == line),
+            "{} in\n{}",
+            line,
+            scraped
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn open_watch_streams_are_tracked() -> anyhow::Result<()> {
+    let metrics = Metrics::default();
+    let mut client = spawn_instrumented_server(&metrics).await?;
+    client.add(todo(1, "watched")).await?;
+
+    let stream = client.watch(TodoIdentifier { id: 1 }).await?.into_inner();
+    wait_for_watches(&metrics, 1).await;
+
+    drop(stream);
+    wait_for_watches(&metrics, 0).await;
+
+    let line = r#"grpc_server_requests_total{code="Cancelled",method="/todos.Todos/Watch"} 1"#;
+    assert!(scrape(&metrics).await.lines().any(|l| l == line));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_methods_share_one_label() -> anyhow::Result<()> {
+    let metrics = Metrics::default();
+    let service = metrics
+        .layer()
+        .layer(TodosServer::new(TodoService::default()));
+    let addr = listen(Server::builder().add_service(service)).await?;
+    let channel = Channel::from_shared(format!("http://{}", addr))?
+        .connect()
+        .await?;
+
+    // the generated client only calls known methods, so call made up ones by hand
+    for method in ["/todos.Todos/Bogus1", "/todos.Todos/Bogus2"] {
+        let mut grpc = Grpc::new(channel.clone());
+        grpc.ready().await?;
+        let status = grpc
+            .unary(
+                tonic::Request::new(TodoIdentifier { id: 1 }),
+                PathAndQuery::from_static(method),
+                ProstCodec::<TodoIdentifier, TodoIdentifier>::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+    }
+
+    let scraped = scrape(&metrics).await;
+    let line = r#"grpc_server_requests_total{code="Unimplemented",method="unknown"} 2"#;
+    assert!(
+        scraped.lines().any(|l| l == line),
+        "{} in\n{}",
+        line,
+        scraped
+    );
+    assert!(!scraped.contains("Bogus"), "{}", scraped);
+
+    Ok(())
+}
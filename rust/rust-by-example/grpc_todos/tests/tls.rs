@@ -0,0 +1,180 @@
+mod common;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::http::{header, Request, StatusCode};
+use common::{connect_tls, pki::Pki, spawn_tls_server, todo};
+use grpc_todos::{
+    auth::Authenticator,
+    gateway,
+    server::TodoService,
+    tls::{self, TlsFiles},
+    TodoListRequest,
+};
+use hyper::{server::accept, Body};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::TlsConnector;
+use tonic::transport::ClientTlsConfig;
+
+/// Kept short so the test for stalled handshakes does not have to wait long.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Writes a certificate issued by `pki` for the server, and the CA when client
+/// certificates are required because `mutual` is set.
+fn files(pki: &Pki, mutual: bool) -> anyhow::Result<TlsFiles> {
+    let server = pki.server()?;
+    Ok(TlsFiles {
+        cert: pki.write("server.pem", &server.cert)?,
+        key: pki.write("server.key", &server.key)?,
+        client_ca: if mutual {
+            Some(pki.write("ca.pem", &pki.ca_pem())?)
+        } else {
+            None
+        },
+    })
+}
+
+async fn spawn(pki: &Pki, mutual: bool) -> anyhow::Result<SocketAddr> {
+    spawn_tls_server(files(pki, mutual)?.grpc_acceptor()?, HANDSHAKE_TIMEOUT).await
+}
+
+/// Serves the REST gateway with the same files as [`spawn`] and returns its address.
+async fn spawn_gateway(pki: &Pki, mutual: bool) -> anyhow::Result<SocketAddr> {
+    let acceptor = files(pki, mutual)?.acceptor()?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let app = gateway::router(TodoService::default(), Authenticator::disabled());
+    tokio::spawn(
+        axum::Server::builder(accept::from_stream(tls::incoming(
+            listener,
+            acceptor,
+            HANDSHAKE_TIMEOUT,
+        )))
+        .serve(app.into_make_service()),
+    );
+
+    Ok(addr)
+}
+
+/// Sends `GET /todos` over `stream` and returns the response status.
+async fn get_todos<S>(stream: S) -> anyhow::Result<StatusCode>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(connection);
+
+    let request = Request::get("/todos")
+        .header(header::HOST, "localhost")
+        .body(Body::empty())?;
+    Ok(sender.send_request(request).await?.status())
+}
+
+/// Connects with `tls` and makes one call, the handshake may only fail on the first request.
+async fn list(addr: std::net::SocketAddr, tls: ClientTlsConfig) -> anyhow::Result<usize> {
+    let mut client = connect_tls(addr, tls).await?;
+    let todos = client.list(TodoListRequest::default()).await?.into_inner();
+    Ok(todos.todos.len())
+}
+
+#[tokio::test]
+async fn clients_trusting_the_ca_can_connect() -> anyhow::Result<()> {
+    let pki = Pki::new("tls")?;
+    let addr = spawn(&pki, false).await?;
+
+    let mut client = connect_tls(addr, pki.client_config(None)).await?;
+    client.add(todo(1, "encrypted")).await?;
+    assert_eq!(list(addr, pki.client_config(None)).await?, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_not_trusting_the_ca_are_refused() -> anyhow::Result<()> {
+    let pki = Pki::new("untrusted")?;
+    let addr = spawn(&pki, false).await?;
+
+    let other = Pki::new("untrusted-other")?;
+    assert!(list(addr, other.client_config(None)).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn mutual_tls_requires_a_client_certificate_from_the_ca() -> anyhow::Result<()> {
+    let pki = Pki::new("mtls")?;
+    let addr = spawn(&pki, true).await?;
+
+    let alice = pki.client("alice")?;
+    assert_eq!(list(addr, pki.client_config(Some(&alice))).await?, 0);
+
+    assert!(list(addr, pki.client_config(None)).await.is_err());
+
+    let mallory = Pki::new("mtls-other")?.client("mallory")?;
+    assert!(list(addr, pki.client_config(Some(&mallory))).await.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn missing_files_are_reported_with_their_path() {
+    let files = TlsFiles {
+        cert: "does/not/exist.pem".into(),
+        key: "does/not/exist.key".into(),
+        client_ca: None,
+    };
+
+    let Err(err) = files.acceptor() else {
+        panic!("missing files were accepted");
+    };
+    assert!(err.to_string().contains("does/not/exist.pem"), "{}", err);
+}
+
+#[tokio::test]
+async fn gateway_is_only_reachable_over_mutual_tls_when_it_is_on() -> anyhow::Result<()> {
+    let pki = Pki::new("gateway-mtls")?;
+    let addr = spawn_gateway(&pki, true).await?;
+
+    // plaintext HTTP is not answered
+    assert!(get_todos(TcpStream::connect(addr).await?).await.is_err());
+
+    let connect = |identity| {
+        let connector = TlsConnector::from(Arc::new(pki.rustls_client_config(identity)?));
+        anyhow::Ok(async move {
+            let stream = TcpStream::connect(addr).await?;
+            let stream = connector.connect("localhost".try_into()?, stream).await?;
+            get_todos(stream).await
+        })
+    };
+
+    assert!(connect(None)?.await.is_err());
+
+    let alice = pki.client("alice")?;
+    assert_eq!(connect(Some(&alice))?.await?, StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_stalling_the_handshake_are_disconnected() -> anyhow::Result<()> {
+    let pki = Pki::new("stalled")?;
+
+    for addr in [spawn(&pki, false).await?, spawn_gateway(&pki, false).await?] {
+        // connects but never sends a ClientHello
+        let mut stream = TcpStream::connect(addr).await?;
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(HANDSHAKE_TIMEOUT * 10, stream.read(&mut buf)).await;
+        assert!(
+            matches!(read, Ok(Ok(0) | Err(_))),
+            "{} did not disconnect: {:?}",
+            addr,
+            read
+        );
+    }
+
+    Ok(())
+}
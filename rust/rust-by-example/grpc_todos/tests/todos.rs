@@ -0,0 +1,285 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use common::{spawn_server, spawn_server_with, todo};
+use grpc_todos::{
+    server::TodoService, TodoIdentifier, TodoListRequest, TodoStatus, TodoStatusUpdateRequest,
+    TodoUpdateRequest,
+};
+use tonic::Code;
+use tonic_types::StatusExt;
+
+#[tokio::test]
+async fn add_get_and_remove() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+
+    let response = client.add(todo(1, "write tests")).await?.into_inner();
+    assert_eq!(response.id, Some(TodoIdentifier { id: 1 }));
+    assert_eq!(response.message, "todo added");
+
+    let fetched = client.get(TodoIdentifier { id: 1 }).await?.into_inner();
+    assert_eq!(fetched, todo(1, "write tests"));
+
+    let status = client.add(todo(1, "again")).await.unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+
+    client.remove(TodoIdentifier { id: 1 }).await?;
+
+    let status = client.get(TodoIdentifier { id: 1 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let status = client.remove(TodoIdentifier { id: 1 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_returns_todos_ordered_by_id() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+
+    for (id, title) in [(3, "c"), (1, "a"), (2, "b")] {
+        client.add(todo(id, title)).await?;
+    }
+
+    let todos = client
+        .list(TodoListRequest::default())
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(todos, [todo(1, "a"), todo(2, "b"), todo(3, "c")]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn update_status_and_fields() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    client.add(todo(1, "write tests")).await?;
+
+    client
+        .update(TodoStatusUpdateRequest {
+            id: Some(TodoIdentifier { id: 1 }),
+            status: TodoStatus::Completed.into(),
+        })
+        .await?;
+
+    let mut patch = todo(1, "write more tests");
+    patch.status = TodoStatus::New.into();
+    client
+        .update_todo(TodoUpdateRequest {
+            todo: Some(patch),
+            update_mask: Some(prost_types::FieldMask {
+                paths: vec!["descriptor.title".into()],
+            }),
+        })
+        .await?;
+
+    let fetched = client.get(TodoIdentifier { id: 1 }).await?.into_inner();
+    assert_eq!(fetched.status, i32::from(TodoStatus::Completed));
+    assert_eq!(fetched.descriptor.unwrap().title, "write more tests");
+
+    let status = client
+        .update(TodoStatusUpdateRequest {
+            id: Some(TodoIdentifier { id: 1 }),
+            status: TodoStatus::New.into(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let status = client
+        .update(TodoStatusUpdateRequest {
+            id: Some(TodoIdentifier { id: 2 }),
+            status: TodoStatus::Ongoing.into(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_requests_carry_field_violations() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+
+    let status = client.add(todo(1, "")).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let fields: Vec<_> = status
+        .get_details_bad_request()
+        .expect("bad request details")
+        .field_violations
+        .into_iter()
+        .map(|violation| violation.field)
+        .collect();
+    assert_eq!(fields, ["descriptor.title"]);
+
+    client.add(todo(1, "write tests")).await?;
+    let status = client
+        .update_todo(TodoUpdateRequest {
+            todo: Some(todo(1, "")),
+            update_mask: Some(prost_types::FieldMask {
+                paths: vec!["descriptor.title".into()],
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_streams_changes_until_removed() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    client.add(todo(1, "write tests")).await?;
+
+    let mut stream = client.watch(TodoIdentifier { id: 1 }).await?.into_inner();
+
+    client
+        .update(TodoStatusUpdateRequest {
+            id: Some(TodoIdentifier { id: 1 }),
+            status: TodoStatus::Ongoing.into(),
+        })
+        .await?;
+
+    let changed = tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await??
+        .expect("watch stream ended early");
+    assert_eq!(changed.status, i32::from(TodoStatus::Ongoing));
+
+    client.remove(TodoIdentifier { id: 1 }).await?;
+
+    let status = tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await?
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let status = client.watch(TodoIdentifier { id: 1 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_ends_watch_streams() -> anyhow::Result<()> {
+    let service = TodoService::default();
+    let mut client = spawn_server_with(service.clone()).await?;
+    client.add(todo(1, "write tests")).await?;
+
+    let mut stream = client.watch(TodoIdentifier { id: 1 }).await?.into_inner();
+
+    service.shutdown();
+
+    let status = tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await?
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+
+    let status = client.watch(TodoIdentifier { id: 1 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn parent_cannot_complete_with_open_subtasks() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    client.add(todo(1, "release")).await?;
+
+    let mut subtask = todo(2, "write changelog");
+    subtask.parent_id = Some(TodoIdentifier { id: 1 });
+    client.add(subtask).await?;
+
+    let complete = |id| TodoStatusUpdateRequest {
+        id: Some(TodoIdentifier { id }),
+        status: TodoStatus::Completed.into(),
+    };
+
+    let status = client.update(complete(1)).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let violations = status
+        .get_details_precondition_failure()
+        .expect("precondition failure details")
+        .violations;
+    assert_eq!(violations[0].subject, "todos/2");
+
+    client.update(complete(2)).await?;
+    client.update(complete(1)).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subtasks_need_an_existing_parent_without_cycles() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+
+    let mut orphan = todo(2, "write changelog");
+    orphan.parent_id = Some(TodoIdentifier { id: 1 });
+    let status = client.add(orphan.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    client.add(todo(1, "release")).await?;
+    client.add(orphan).await?;
+
+    let mut patch = todo(1, "release");
+    patch.parent_id = Some(TodoIdentifier { id: 2 });
+    let status = client
+        .update_todo(TodoUpdateRequest {
+            todo: Some(patch),
+            update_mask: Some(prost_types::FieldMask {
+                paths: vec!["parent_id".into()],
+            }),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_filters_by_tag_and_overdue() -> anyhow::Result<()> {
+    let mut client = spawn_server().await?;
+    let now = SystemTime::now();
+
+    let mut late = todo(1, "pay rent");
+    late.tags = vec!["home".into()];
+    late.due_at = Some((now - Duration::from_secs(3600)).into());
+    client.add(late.clone()).await?;
+
+    let mut done = todo(2, "file taxes");
+    done.tags = vec!["home".into()];
+    done.due_at = late.due_at.clone();
+    done.status = TodoStatus::Completed.into();
+    client.add(done.clone()).await?;
+
+    let mut upcoming = todo(3, "review PR");
+    upcoming.tags = vec!["work".into()];
+    upcoming.due_at = Some((now + Duration::from_secs(3600)).into());
+    client.add(upcoming).await?;
+
+    let home = client
+        .list(TodoListRequest {
+            tag: Some("home".into()),
+            overdue: false,
+        })
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(home, [late.clone(), done]);
+
+    let overdue = client
+        .list(TodoListRequest {
+            tag: None,
+            overdue: true,
+        })
+        .await?
+        .into_inner()
+        .todos;
+    assert_eq!(overdue, [late]);
+
+    Ok(())
+}
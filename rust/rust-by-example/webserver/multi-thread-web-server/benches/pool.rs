@@ -0,0 +1,207 @@
+//! Throughput of many tiny jobs on the work-stealing pool, compared with the classic pool
+//! that shares one `mpsc` receiver behind a mutex: queued from outside the pool, and queued
+//! by the jobs themselves, which the work-stealing pool keeps on their worker's deque.
+
+use std::{
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use multi_thread_web_server::ThreadPool;
+
+const THREADS: usize = 4;
+
+/// The pool as it was before work stealing: every worker locks the same receiver.
+struct MutexPool {
+    sender: Option<mpsc::Sender<Box<dyn FnOnce() + Send>>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl MutexPool {
+    fn new(size: usize) -> MutexPool {
+        let (sender, receiver) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+
+        MutexPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    fn execute(&self, f: impl FnOnce() + Send + 'static) {
+        self.sender.as_ref().unwrap().send(Box::new(f)).unwrap();
+    }
+}
+
+impl Drop for MutexPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+/// What the recursive benchmark needs from either pool.
+trait Spawn: Send + Sync + 'static {
+    fn spawn(&self, f: impl FnOnce() + Send + 'static);
+}
+
+impl Spawn for MutexPool {
+    fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        self.execute(f);
+    }
+}
+
+impl Spawn for ThreadPool {
+    fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        self.execute(f).unwrap();
+    }
+}
+
+/// Splits into two jobs until `depth` runs out, like a divide and conquer algorithm, so all
+/// but the first two jobs are queued by other jobs.
+fn fork<P: Spawn>(pool: &Arc<P>, depth: u32, latch: &Arc<Latch>) {
+    if depth == 0 {
+        latch.count_down();
+        return;
+    }
+
+    for _ in 0..2 {
+        let (child_pool, latch) = (Arc::clone(pool), Arc::clone(latch));
+        pool.spawn(move || fork(&child_pool, depth - 1, &latch));
+    }
+}
+
+/// Drops `pool` once the jobs of the last iteration let go of it, as a worker dropping the
+/// last handle would have to join itself.
+fn drop_when_idle<P>(pool: Arc<P>) {
+    while Arc::strong_count(&pool) > 1 {
+        thread::yield_now();
+    }
+    drop(pool);
+}
+
+/// Counts down the jobs of one iteration.
+struct Latch {
+    remaining: Mutex<usize>,
+    done: Condvar,
+}
+
+impl Latch {
+    fn new(count: usize) -> Arc<Latch> {
+        Arc::new(Latch {
+            remaining: Mutex::new(count),
+            done: Condvar::new(),
+        })
+    }
+
+    fn count_down(&self) {
+        let mut remaining = self.remaining.lock().unwrap();
+        *remaining -= 1;
+        if *remaining == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    fn wait(&self) {
+        let remaining = self.remaining.lock().unwrap();
+        drop(
+            self.done
+                .wait_while(remaining, |remaining| *remaining > 0)
+                .unwrap(),
+        );
+    }
+}
+
+fn tiny_jobs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tiny_jobs");
+
+    for jobs in [1_000, 10_000] {
+        group.throughput(Throughput::Elements(jobs as u64));
+
+        let pool = MutexPool::new(THREADS);
+        group.bench_with_input(BenchmarkId::new("mutex", jobs), &jobs, |b, &jobs| {
+            b.iter(|| {
+                let latch = Latch::new(jobs);
+                for _ in 0..jobs {
+                    let latch = Arc::clone(&latch);
+                    pool.execute(move || latch.count_down());
+                }
+                latch.wait();
+            })
+        });
+        drop(pool);
+
+        let pool = ThreadPool::new(THREADS);
+        group.bench_with_input(
+            BenchmarkId::new("work_stealing", jobs),
+            &jobs,
+            |b, &jobs| {
+                b.iter(|| {
+                    let latch = Latch::new(jobs);
+                    for _ in 0..jobs {
+                        let latch = Arc::clone(&latch);
+                        pool.execute(move || latch.count_down()).unwrap();
+                    }
+                    latch.wait();
+                })
+            },
+        );
+        drop(pool);
+    }
+
+    group.finish();
+}
+
+fn recursive_jobs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("recursive_jobs");
+
+    for depth in [10, 13] {
+        // every job but the root, which runs on the benchmark thread
+        group.throughput(Throughput::Elements((1 << (depth + 1)) - 2));
+
+        let pool = Arc::new(MutexPool::new(THREADS));
+        group.bench_with_input(BenchmarkId::new("mutex", depth), &depth, |b, &depth| {
+            b.iter(|| {
+                let latch = Latch::new(1 << depth);
+                fork(&pool, depth, &latch);
+                latch.wait();
+            })
+        });
+        drop_when_idle(pool);
+
+        let pool = Arc::new(ThreadPool::new(THREADS));
+        group.bench_with_input(
+            BenchmarkId::new("work_stealing", depth),
+            &depth,
+            |b, &depth| {
+                b.iter(|| {
+                    let latch = Latch::new(1 << depth);
+                    fork(&pool, depth, &latch);
+                    latch.wait();
+                })
+            },
+        );
+        drop_when_idle(pool);
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, tiny_jobs, recursive_jobs);
+criterion_main!(benches);
@@ -0,0 +1,233 @@
+use std::time::Duration;
+
+use crate::ThreadPool;
+
+/// What [`ThreadPool::execute`] does with a job when the queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RejectionPolicy {
+    /// Wait until a worker takes a job off the queue.
+    ///
+    /// A job running on the pool does not wait for itself but queues the job anyway.
+    #[default]
+    Block,
+    /// Return [`ExecuteError::QueueFull`](crate::ExecuteError::QueueFull).
+    Reject,
+    /// Drop the job that has been queued the longest to make room.
+    DropOldest,
+    /// Run the job right away on the thread calling `execute`.
+    ///
+    /// A job running on the pool queues the job anyway, so it still counts towards growing
+    /// the pool.
+    CallerRuns,
+}
+
+/// Configures a [`ThreadPool`].
+///
+/// ```
+/// use multi_thread_web_server::{RejectionPolicy, ThreadPool};
+///
+/// let pool = ThreadPool::builder()
+///     .core_threads(2)
+///     .max_threads(8)
+///     .queue_capacity(100)
+///     .rejection_policy(RejectionPolicy::Reject)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    pub(crate) core_threads: usize,
+    pub(crate) max_threads: usize,
+    pub(crate) idle_timeout: Duration,
+    pub(crate) queue_capacity: Option<usize>,
+    pub(crate) rejection_policy: RejectionPolicy,
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    /// A pool with a fixed thread per CPU and an unbounded queue.
+    pub fn new() -> Builder {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        Builder {
+            core_threads: threads,
+            max_threads: threads,
+            idle_timeout: Duration::from_secs(60),
+            queue_capacity: None,
+            rejection_policy: RejectionPolicy::default(),
+        }
+    }
+
+    /// Keeps exactly `threads` workers.
+    pub fn threads(mut self, threads: usize) -> Builder {
+        self.core_threads = threads;
+        self.max_threads = threads;
+        self
+    }
+
+    /// Workers started with the pool, which stay around while idle.
+    pub fn core_threads(mut self, threads: usize) -> Builder {
+        self.core_threads = threads;
+        self
+    }
+
+    /// Upper limit on workers, raised to the core size if lower.
+    ///
+    /// Workers beyond the core size are started when no worker is idle and more jobs are
+    /// queued than workers are looking for their next one.
+    pub fn max_threads(mut self, threads: usize) -> Builder {
+        self.max_threads = threads;
+        self
+    }
+
+    /// How long a worker beyond the core size may wait for a job before it stops.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Builder {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Limits the number of jobs waiting for a worker.
+    pub fn queue_capacity(mut self, capacity: usize) -> Builder {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    pub fn rejection_policy(mut self, policy: RejectionPolicy) -> Builder {
+        self.rejection_policy = policy;
+        self
+    }
+
+    /// Starts the pool.
+    ///
+    /// # Panics
+    ///
+    /// If the maximum number of threads or the queue capacity is zero.
+    pub fn build(self) -> ThreadPool {
+        ThreadPool::from_builder(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+    };
+
+    use super::*;
+    use crate::{
+        tests::{busy_pool, wait_until},
+        ExecuteError,
+    };
+
+    /// Queues one job that fills the queue of a [`busy_pool`] and one that does not fit,
+    /// each sending its name once it runs.
+    fn overflow(pool: &ThreadPool) -> (mpsc::Receiver<&'static str>, Result<(), ExecuteError>) {
+        let (ran, runs) = mpsc::channel();
+        let first = ran.clone();
+        pool.execute(move || first.send("first").unwrap()).unwrap();
+        let second = pool.execute(move || ran.send("second").unwrap());
+        (runs, second)
+    }
+
+    /// Queues two jobs from a job on a pool with one worker and room for one job, so the
+    /// second does not fit, and returns the order they and the queuing job finished in.
+    fn overflow_from_a_job(policy: RejectionPolicy) -> Vec<&'static str> {
+        let pool = Arc::new(
+            ThreadPool::builder()
+                .threads(1)
+                .queue_capacity(1)
+                .rejection_policy(policy)
+                .build(),
+        );
+        let (ran, runs) = mpsc::channel();
+
+        let inner = Arc::clone(&pool);
+        pool.execute(move || {
+            for name in ["first", "second"] {
+                let ran = ran.clone();
+                inner.execute(move || ran.send(name).unwrap()).unwrap();
+            }
+            ran.send("queuing").unwrap();
+        })
+        .unwrap();
+
+        let mut ran: Vec<_> = (0..3)
+            .map(|_| runs.recv_timeout(Duration::from_secs(5)).expect("stuck"))
+            .collect();
+        ran[1..].sort();
+        // a worker cannot join itself, so the last handle on the pool must be ours
+        wait_until(|| Arc::strong_count(&pool) == 1);
+        ran
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (pool, release) = busy_pool(RejectionPolicy::Block);
+
+        thread::scope(|s| {
+            let queuing = s.spawn(|| overflow(&pool));
+            thread::sleep(Duration::from_millis(50));
+            assert!(!queuing.is_finished());
+
+            drop(release);
+            let (runs, second) = queuing.join().unwrap();
+            assert_eq!(second, Ok(()));
+            let mut ran: Vec<_> = runs.iter().take(2).collect();
+            ran.sort();
+            assert_eq!(ran, ["first", "second"]);
+        });
+    }
+
+    #[test]
+    fn reject_returns_an_error() {
+        let (pool, release) = busy_pool(RejectionPolicy::Reject);
+
+        let (runs, second) = overflow(&pool);
+        assert_eq!(second, Err(ExecuteError::QueueFull));
+        assert_eq!(pool.stats().queued, 1);
+
+        drop(release);
+        assert_eq!(runs.iter().collect::<Vec<_>>(), ["first"]);
+    }
+
+    #[test]
+    fn drop_oldest_makes_room() {
+        let (pool, release) = busy_pool(RejectionPolicy::DropOldest);
+
+        let (runs, second) = overflow(&pool);
+        assert_eq!(second, Ok(()));
+        assert_eq!(pool.stats().queued, 1);
+
+        drop(release);
+        assert_eq!(runs.iter().collect::<Vec<_>>(), ["second"]);
+    }
+
+    #[test]
+    fn caller_runs_runs_the_job_right_away() {
+        let (pool, release) = busy_pool(RejectionPolicy::CallerRuns);
+
+        let (runs, second) = overflow(&pool);
+        assert_eq!(second, Ok(()));
+        assert_eq!(runs.try_recv(), Ok("second"));
+
+        drop(release);
+        assert_eq!(runs.iter().collect::<Vec<_>>(), ["first"]);
+    }
+
+    #[test]
+    fn jobs_queue_jobs_past_the_capacity_instead_of_blocking_or_running_them() {
+        for policy in [RejectionPolicy::Block, RejectionPolicy::CallerRuns] {
+            assert_eq!(
+                overflow_from_a_job(policy),
+                ["queuing", "first", "second"],
+                "{:?}",
+                policy
+            );
+        }
+    }
+}
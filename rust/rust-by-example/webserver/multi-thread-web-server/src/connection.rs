@@ -0,0 +1,493 @@
+//! Serves the requests arriving on one connection.
+
+use std::{
+    fmt,
+    io::{self, Read},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::http::{Method, ReadError, Request, RequestParser, Response, StatusCode, Version};
+use crate::router::Router;
+
+/// How long connections are kept open, how many requests they may carry, and how long and
+/// large those requests may be.
+///
+/// The timeouts bound how long one slow client can hold on to the worker serving it: a client
+/// that trickles in a request a byte at a time is cut off once the header or body timeout runs
+/// out, not whenever it stops sending.
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+    pub(crate) idle_timeout: Duration,
+    pub(crate) max_requests: usize,
+    pub(crate) header_timeout: Duration,
+    pub(crate) body_timeout: Duration,
+    pub(crate) write_timeout: Duration,
+    pub(crate) max_header_size: usize,
+    pub(crate) max_body_size: usize,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> ConnectionConfig {
+        ConnectionConfig {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            header_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            max_header_size: 8 * 1024,
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    pub fn new() -> ConnectionConfig {
+        ConnectionConfig::default()
+    }
+
+    /// How long a kept-alive connection may wait for its next request before it is closed.
+    pub fn idle_timeout(mut self, timeout: Duration) -> ConnectionConfig {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// How many requests a connection may carry before it is closed, at least one.
+    pub fn max_requests(mut self, requests: usize) -> ConnectionConfig {
+        self.max_requests = requests.max(1);
+        self
+    }
+
+    /// How long a client has to send the request line and headers once it starts a request.
+    pub fn header_timeout(mut self, timeout: Duration) -> ConnectionConfig {
+        self.header_timeout = timeout;
+        self
+    }
+
+    /// How long a client has to send the body once the headers have arrived.
+    pub fn body_timeout(mut self, timeout: Duration) -> ConnectionConfig {
+        self.body_timeout = timeout;
+        self
+    }
+
+    /// How long a write of a response may block before the connection is given up on.
+    pub fn write_timeout(mut self, timeout: Duration) -> ConnectionConfig {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// How many bytes the request line and headers may take, answered with a 431 if exceeded.
+    pub fn max_header_size(mut self, size: usize) -> ConnectionConfig {
+        self.max_header_size = size;
+        self
+    }
+
+    /// How many bytes a request body may take, answered with a 413 if exceeded.
+    pub fn max_body_size(mut self, size: usize) -> ConnectionConfig {
+        self.max_body_size = size;
+        self
+    }
+}
+
+/// Caps how many connections are served at once.
+///
+/// Each connection kept alive holds on to a worker, so past the limit new connections are
+/// answered with a 503 by [`reject_connection`] rather than left waiting for one.
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    open: AtomicUsize,
+    max: usize,
+}
+
+/// A slot taken from a [`ConnectionLimit`], given back when dropped.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limit: Arc<ConnectionLimit>,
+}
+
+impl ConnectionLimit {
+    pub fn new(max: usize) -> Arc<ConnectionLimit> {
+        Arc::new(ConnectionLimit {
+            open: AtomicUsize::new(0),
+            max,
+        })
+    }
+
+    /// Takes a slot for a new connection, `None` if all of them are taken.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConnectionPermit> {
+        self.open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (open < self.max).then_some(open + 1)
+            })
+            .ok()?;
+        Some(ConnectionPermit {
+            limit: Arc::clone(self),
+        })
+    }
+
+    /// How many connections hold a slot.
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.limit.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Answers a connection over the [`ConnectionLimit`] with a 503 and closes it.
+///
+/// This is meant to run on the accepting thread, so the write is bounded by the write timeout
+/// and the request is not read.
+pub fn reject_connection(mut stream: TcpStream, config: &ConnectionConfig) {
+    if stream
+        .set_write_timeout(Some(config.write_timeout))
+        .is_err()
+    {
+        return;
+    }
+    let response = Response::new(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("Connection", "close")
+        .header("Retry-After", 1)
+        .body("Too many connections\n");
+    let _ = response.write_to(&mut stream);
+}
+
+/// Answers the requests on `stream` with `router` until the connection is to be closed.
+///
+/// Connections are kept alive as HTTP/1.1 has it by default, and as HTTP/1.0 clients can ask
+/// for. Requests the client pipelined are answered in order. The connection is closed when
+/// either side sends `Connection: close`, after `max_requests`, when no request arrives
+/// within the idle timeout, or when a request cannot be parsed or is not sent in time.
+pub fn serve_connection(mut stream: TcpStream, router: &Router, config: &ConnectionConfig) {
+    // responses are written whole, so there is nothing to gain from delaying small ones
+    let _ = stream.set_nodelay(true);
+    if let Err(err) = stream.set_write_timeout(Some(config.write_timeout)) {
+        println!("Failed to set the write timeout: {}", err);
+        return;
+    }
+
+    let mut parser = RequestParser::new()
+        .max_head_size(config.max_header_size)
+        .max_body_size(config.max_body_size);
+
+    for served in 1.. {
+        let request = match read_request(&mut stream, &mut parser, config) {
+            Ok(Some(request)) => request,
+            // the client closed the connection between requests
+            Ok(None) => return,
+            Err(ReadError::Io(err)) if is_timeout(&err) && parser.is_empty() => return,
+            Err(ReadError::Io(err)) if is_timeout(&err) => {
+                let _ = error_response(StatusCode::REQUEST_TIMEOUT, "request timed out")
+                    .write_to(&mut stream);
+                return;
+            }
+            Err(ReadError::Io(err)) => {
+                println!("Failed to read request: {}", err);
+                return;
+            }
+            Err(ReadError::Parse(err)) => {
+                let _ = error_response(err.status(), err).write_to(&mut stream);
+                return;
+            }
+        };
+
+        let mut response = router.handle(&request);
+        let keep_alive = served < config.max_requests
+            && wants_keep_alive(&request)
+            && !has_token(response.headers().get("Connection"), "close");
+
+        if keep_alive {
+            response.headers_mut().insert("Connection", "keep-alive");
+            response.headers_mut().insert(
+                "Keep-Alive",
+                format!(
+                    "timeout={}, max={}",
+                    config.idle_timeout.as_secs(),
+                    config.max_requests - served
+                ),
+            );
+        } else {
+            response.headers_mut().insert("Connection", "close");
+        }
+
+        let result = if request.method == Method::Head {
+            response.write_head_to(&mut stream)
+        } else {
+            response.write_to(&mut stream)
+        };
+        if let Err(err) = result {
+            println!("Failed to send response: {}", err);
+            return;
+        }
+        if !keep_alive {
+            return;
+        }
+    }
+}
+
+/// Reads the next request, giving the client the idle timeout to start it and the header and
+/// body timeouts to finish each part.
+fn read_request(
+    stream: &mut TcpStream,
+    parser: &mut RequestParser,
+    config: &ConnectionConfig,
+) -> Result<Option<Request>, ReadError> {
+    let mut chunk = [0; 4096];
+    let mut head_deadline = None;
+    let mut body_deadline = None;
+
+    loop {
+        if let Some(request) = parser.parse()? {
+            return Ok(Some(request));
+        }
+
+        let timeout = if parser.is_empty() {
+            config.idle_timeout
+        } else {
+            let deadline = if parser.awaits_body() {
+                body_deadline.get_or_insert_with(|| Instant::now() + config.body_timeout)
+            } else {
+                head_deadline.get_or_insert_with(|| Instant::now() + config.header_timeout)
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+            }
+            remaining
+        };
+        stream.set_read_timeout(Some(timeout))?;
+
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            if parser.is_empty() {
+                return Ok(None);
+            }
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        parser.feed(&chunk[..read]);
+    }
+}
+
+fn error_response(status: StatusCode, message: impl fmt::Display) -> Response {
+    Response::new(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("Connection", "close")
+        .body(format!("{}\n", message))
+}
+
+fn wants_keep_alive(request: &Request) -> bool {
+    let connection = request.header("Connection");
+    match request.version {
+        Version::Http11 => !has_token(connection, "close"),
+        Version::Http10 => has_token(connection, "keep-alive"),
+    }
+}
+
+/// Whether the comma separated `header` value lists `token`.
+fn has_token(header: Option<&str>, token: &str) -> bool {
+    header.is_some_and(|header| {
+        header
+            .split(',')
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    })
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::http::StatusCode;
+
+    /// Serves one connection on a background thread and returns the client side of it.
+    fn connect(config: ConnectionConfig) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let router = Router::new().get("/:name", |_, params| {
+                Response::new(StatusCode::OK).body(params.get("name").unwrap().to_string())
+            });
+            let (stream, _) = listener.accept().unwrap();
+            serve_connection(stream, &router, &config);
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    /// Reads until the server closes the connection.
+    fn read_all(stream: &mut TcpStream) -> String {
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        received
+    }
+
+    #[test]
+    fn answers_pipelined_requests_in_order() {
+        let mut stream = connect(ConnectionConfig::new());
+        stream
+            .write_all(
+                b"GET /one HTTP/1.1\r\n\r\nGET /two HTTP/1.1\r\n\r\n\
+                  GET /three HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+
+        let received = read_all(&mut stream);
+        let bodies: Vec<&str> = received
+            .split("HTTP/1.1 200 OK")
+            .skip(1)
+            .map(|response| response.rsplit("\r\n\r\n").next().unwrap())
+            .collect();
+        assert_eq!(bodies, ["one", "two", "three"]);
+        assert_eq!(received.matches("Connection: keep-alive").count(), 2);
+        assert!(received.contains("Connection: close"));
+    }
+
+    #[test]
+    fn closes_after_max_requests() {
+        let mut stream = connect(ConnectionConfig::new().max_requests(2));
+        stream
+            .write_all(b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\n\r\n")
+            .unwrap();
+
+        let received = read_all(&mut stream);
+        assert!(received.contains("Keep-Alive: timeout=5, max=1"));
+        assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(received.ends_with("b"));
+    }
+
+    #[test]
+    fn closes_http_10_connections_unless_asked_to_keep_them() {
+        let mut stream = connect(ConnectionConfig::new());
+        stream
+            .write_all(b"GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.0\r\n\r\nGET /c HTTP/1.0\r\n\r\n")
+            .unwrap();
+
+        let received = read_all(&mut stream);
+        assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(received.ends_with("b"));
+    }
+
+    #[test]
+    fn closes_idle_connections() {
+        let mut stream = connect(ConnectionConfig::new().idle_timeout(Duration::from_millis(100)));
+        stream.write_all(b"GET /a HTTP/1.1\r\n\r\n").unwrap();
+
+        let start = Instant::now();
+        let received = read_all(&mut stream);
+        assert!(received.ends_with("a"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn times_out_requests_sent_too_slowly() {
+        let config = ConnectionConfig::new().header_timeout(Duration::from_millis(200));
+        let mut stream = connect(config);
+
+        // every byte arrives well within the idle timeout, the whole head does not
+        let start = Instant::now();
+        for byte in b"GET /a HTTP/1.1\r\nX: " {
+            if stream.write_all(&[*byte]).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let received = read_all(&mut stream);
+        assert!(received.starts_with("HTTP/1.1 408 Request Timeout"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn times_out_bodies_sent_too_slowly() {
+        let config = ConnectionConfig::new().body_timeout(Duration::from_millis(100));
+        let mut stream = connect(config);
+        stream
+            .write_all(b"POST /a HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc")
+            .unwrap();
+
+        let received = read_all(&mut stream);
+        assert!(received.starts_with("HTTP/1.1 408 Request Timeout"));
+    }
+
+    #[test]
+    fn rejects_headers_over_the_size_limit() {
+        let mut stream = connect(ConnectionConfig::new().max_header_size(64));
+        stream.write_all(b"GET /a HTTP/1.1\r\nCookie: ").unwrap();
+        stream.write_all(&[b'a'; 64]).unwrap();
+
+        let received = read_all(&mut stream);
+        assert!(received.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+    }
+
+    #[test]
+    fn rejects_bodies_over_the_size_limit() {
+        let mut stream = connect(ConnectionConfig::new().max_body_size(16));
+        stream
+            .write_all(b"POST /a HTTP/1.1\r\nContent-Length: 17\r\n\r\n")
+            .unwrap();
+
+        let received = read_all(&mut stream);
+        assert!(received.starts_with("HTTP/1.1 413 Content Too Large"));
+    }
+
+    #[test]
+    fn limits_open_connections() {
+        let limit = ConnectionLimit::new(2);
+        let first = limit.try_acquire().unwrap();
+        let _second = limit.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+        assert_eq!(limit.open(), 2);
+
+        drop(first);
+        assert!(limit.try_acquire().is_some());
+    }
+
+    #[test]
+    fn answers_rejected_connections_with_503() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        reject_connection(stream, &ConnectionConfig::new());
+
+        let received = read_all(&mut client);
+        assert!(received.starts_with("HTTP/1.1 503 Service Unavailable"));
+        assert!(received.contains("Retry-After: 1"));
+    }
+
+    #[test]
+    fn closes_after_a_malformed_request() {
+        let mut stream = connect(ConnectionConfig::new());
+        stream
+            .write_all(b"GET /a HTTP/1.1\r\n\r\nNOT HTTP\r\n\r\nGET /b HTTP/1.1\r\n\r\n")
+            .unwrap();
+
+        let received = read_all(&mut stream);
+        assert!(received.contains("HTTP/1.1 400 Bad Request"));
+        assert!(!received.ends_with("b"));
+    }
+}
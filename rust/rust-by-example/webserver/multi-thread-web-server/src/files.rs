@@ -0,0 +1,502 @@
+//! Serves the files under a document root.
+
+use std::{
+    fs::File,
+    io::{self, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::http::{format_date, parse_date, Method, Request, Response, StatusCode};
+
+/// Answers requests with the files under a directory.
+///
+/// Paths are resolved below the root only: a `..` segment is answered with 403, as is a
+/// symbolic link leading out of the root. A directory is served through its index file.
+/// Responses carry `Last-Modified` and `ETag`, so clients can revalidate with
+/// `If-Modified-Since` and `If-None-Match`, and a single byte range can be requested with
+/// `Range`.
+///
+/// ```no_run
+/// use multi_thread_web_server::files::StaticFiles;
+/// use multi_thread_web_server::router::Router;
+///
+/// let files = StaticFiles::new("public").unwrap();
+/// let router = Router::new().get("/*path", move |request, params| {
+///     files.serve(request, params.get("path").unwrap_or_default())
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index: String,
+}
+
+impl StaticFiles {
+    /// Serves the files under `root`, which must be an existing directory.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<StaticFiles> {
+        let root = root.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", root.display()),
+            ));
+        }
+
+        Ok(StaticFiles {
+            root,
+            index: String::from("index.html"),
+        })
+    }
+
+    /// The file served for a directory, `index.html` by default.
+    pub fn index(mut self, index: impl Into<String>) -> StaticFiles {
+        self.index = index.into();
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Answers `request` with the file at `path`, relative to the root and already
+    /// percent-decoded, as captured by a [`Router`](crate::router::Router) wildcard.
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        if !matches!(request.method, Method::Get | Method::Head) {
+            return Response::new(StatusCode::METHOD_NOT_ALLOWED).header("Allow", "GET, HEAD");
+        }
+
+        let mut file = match self.resolve(path) {
+            Ok(file) => file,
+            Err(status) => return error(status),
+        };
+
+        if file.is_dir() {
+            // relative links in the index resolve against the directory only with the slash
+            if !request.path.ends_with('/') {
+                // a path starting with `//` would make the location point to another host
+                let mut location = format!("/{}/", request.path.trim_start_matches('/'));
+                if let Some(query) = &request.query {
+                    location = format!("{}?{}", location, query);
+                }
+                return Response::new(StatusCode::MOVED_PERMANENTLY).header("Location", location);
+            }
+            file.push(&self.index);
+        }
+
+        match send_file(request, &file) {
+            Ok(response) => response,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => error(StatusCode::NOT_FOUND),
+            Err(err) => {
+                println!("Failed to serve {}: {}", file.display(), err);
+                error(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+
+    /// Maps a request path to a path below the root.
+    fn resolve(&self, path: &str) -> Result<PathBuf, StatusCode> {
+        let mut file = self.root.clone();
+
+        for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+            if segment == "." || segment == ".." || segment.contains(['\\', '\0']) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            file.push(segment);
+        }
+
+        match file.canonicalize() {
+            Ok(resolved) if resolved.starts_with(&self.root) => Ok(resolved),
+            Ok(_) => Err(StatusCode::FORBIDDEN),
+            Err(_) => Err(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+fn error(status: StatusCode) -> Response {
+    Response::new(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(format!("{}\n", status))
+}
+
+fn send_file(request: &Request, path: &Path) -> io::Result<Response> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    let len = metadata.len();
+    let modified = metadata.modified().ok().map(truncate_to_secs);
+    let etag = format!("\"{:x}-{:x}\"", len, modified.map_or(0, secs_since_epoch));
+    let last_modified = modified.map(format_date);
+
+    let mut response = Response::new(StatusCode::OK)
+        .header("ETag", &etag)
+        .header("Accept-Ranges", "bytes");
+    if let Some(last_modified) = &last_modified {
+        response = response.header("Last-Modified", last_modified);
+    }
+
+    if is_fresh(request, &etag, modified) {
+        let mut not_modified = Response::new(StatusCode::NOT_MODIFIED);
+        for (name, value) in response.headers().iter() {
+            if name != "Accept-Ranges" {
+                not_modified.headers_mut().insert(name, value);
+            }
+        }
+        return Ok(not_modified);
+    }
+
+    response = response.header("Content-Type", mime_type(path));
+
+    let range = request
+        .header("Range")
+        .filter(|_| if_range_matches(request, &etag, last_modified.as_deref()))
+        .map(|range| parse_range(range, len));
+
+    match range {
+        Some(ByteRange::Unsatisfiable) => Ok(error(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("Content-Range", format!("bytes */{}", len))),
+        Some(ByteRange::Satisfiable(start, end)) => {
+            file.seek(SeekFrom::Start(start))?;
+
+            let mut response = response
+                .header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+                .stream(file, end - start + 1);
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            Ok(response)
+        }
+        Some(ByteRange::Ignored) | None => Ok(response.stream(file, len)),
+    }
+}
+
+/// Whether the client's cached copy is current, so a 304 will do.
+fn is_fresh(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    // If-None-Match takes precedence when both are sent
+    if let Some(tags) = request.header("If-None-Match") {
+        return tags.trim() == "*"
+            || tags
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag);
+    }
+
+    match (
+        request.header("If-Modified-Since").and_then(parse_date),
+        modified,
+    ) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Whether a `Range` request still applies, which `If-Range` limits to an unchanged file.
+fn if_range_matches(request: &Request, etag: &str, last_modified: Option<&str>) -> bool {
+    match request.header("If-Range") {
+        None => true,
+        Some(condition) if condition.starts_with('"') => condition == etag,
+        Some(condition) => Some(condition) == last_modified,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// The first and last byte to send.
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    /// A range this server does not serve, such as several at once; the whole file is sent.
+    Ignored,
+}
+
+fn parse_range(range: &str, len: u64) -> ByteRange {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return ByteRange::Ignored;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Ignored;
+    };
+    if spec.contains(',') {
+        return ByteRange::Ignored;
+    }
+
+    let parse = |digits: &str| {
+        (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| digits.parse::<u64>().ok())
+            .flatten()
+    };
+
+    match (start, end) {
+        // the last `suffix` bytes
+        ("", suffix) => match parse(suffix) {
+            Some(0) => ByteRange::Unsatisfiable,
+            Some(_) if len == 0 => ByteRange::Unsatisfiable,
+            Some(suffix) => ByteRange::Satisfiable(len.saturating_sub(suffix), len - 1),
+            None => ByteRange::Ignored,
+        },
+        (start, end) => {
+            let Some(start) = parse(start) else {
+                return ByteRange::Ignored;
+            };
+            let end = match end {
+                "" => None,
+                end => match parse(end) {
+                    Some(end) if end >= start => Some(end),
+                    _ => return ByteRange::Ignored,
+                },
+            };
+
+            if start >= len {
+                return ByteRange::Unsatisfiable;
+            }
+            ByteRange::Satisfiable(start, end.map_or(len - 1, |end| end.min(len - 1)))
+        }
+    }
+}
+
+/// The media type of a file, by its extension.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+// HTTP dates have whole seconds, so compare modification times at that precision
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    UNIX_EPOCH + std::time::Duration::from_secs(secs_since_epoch(time))
+}
+
+fn secs_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::http::{Headers, Version};
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut request_headers = Headers::new();
+        for (name, value) in headers {
+            request_headers.append(*name, *value);
+        }
+
+        Request {
+            method: Method::Get,
+            path: path.to_string(),
+            query: None,
+            version: Version::Http11,
+            headers: request_headers,
+            body: Vec::new(),
+        }
+    }
+
+    /// A fresh document root with an index, a text file and a subdirectory.
+    fn document_root(name: &str) -> StaticFiles {
+        let root = std::env::temp_dir().join(format!(
+            "multi-thread-web-server-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("index.html"), "<h1>index</h1>").unwrap();
+        fs::write(root.join("docs/notes.txt"), "0123456789").unwrap();
+        fs::write(root.join("docs/index.html"), "docs").unwrap();
+
+        StaticFiles::new(&root).unwrap()
+    }
+
+    fn serve(files: &StaticFiles, path: &str, headers: &[(&str, &str)]) -> Response {
+        let request = request(path, headers);
+        files.serve(&request, path.trim_start_matches('/'))
+    }
+
+    /// The body as sent, file bodies are only read when the response is written.
+    fn body(response: Response) -> Vec<u8> {
+        let mut sent = Vec::new();
+        response.write_to(&mut sent).unwrap();
+        let head = sent.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        sent.split_off(head + 4)
+    }
+
+    #[test]
+    fn serves_files_with_their_media_type() {
+        let files = document_root("files");
+
+        let response = serve(&files, "/docs/notes.txt", &[]);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+        assert!(response.headers().contains("ETag"));
+        assert!(response.headers().contains("Last-Modified"));
+        // streamed from the file as it is sent
+        assert!(response.body_bytes().is_empty());
+        assert_eq!(body(response), b"0123456789");
+
+        assert_eq!(
+            serve(&files, "/missing.txt", &[]).status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn serves_directory_indexes() {
+        let files = document_root("index");
+
+        assert_eq!(body(serve(&files, "/", &[])), b"<h1>index</h1>");
+        assert_eq!(body(serve(&files, "/docs/", &[])), b"docs");
+
+        let response = serve(&files, "/docs", &[]);
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers().get("Location"), Some("/docs/"));
+
+        let response = serve(&files, "//docs", &[]);
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers().get("Location"), Some("/docs/"));
+    }
+
+    #[test]
+    fn rejects_paths_leaving_the_root() {
+        let files = document_root("traversal");
+
+        for path in [
+            "/../etc/passwd",
+            "/docs/../../etc/passwd",
+            "/docs/./notes.txt",
+            "/a\\b",
+        ] {
+            assert_eq!(
+                serve(&files, path, &[]).status(),
+                StatusCode::FORBIDDEN,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn answers_revalidation_with_304() {
+        let files = document_root("conditional");
+        let response = serve(&files, "/docs/notes.txt", &[]);
+        let etag = response.headers().get("ETag").unwrap().to_string();
+        let last_modified = response.headers().get("Last-Modified").unwrap().to_string();
+
+        let response = serve(&files, "/docs/notes.txt", &[("If-None-Match", &etag)]);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body_bytes().is_empty());
+
+        let response = serve(
+            &files,
+            "/docs/notes.txt",
+            &[("If-Modified-Since", &last_modified)],
+        );
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = serve(
+            &files,
+            "/docs/notes.txt",
+            &[
+                ("If-None-Match", "\"stale\""),
+                ("If-Modified-Since", &last_modified),
+            ],
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = serve(
+            &files,
+            "/docs/notes.txt",
+            &[("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")],
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn serves_byte_ranges() {
+        let files = document_root("ranges");
+
+        let response = serve(&files, "/docs/notes.txt", &[("Range", "bytes=2-4")]);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get("Content-Range"),
+            Some("bytes 2-4/10")
+        );
+        assert_eq!(body(response), b"234");
+
+        let response = serve(&files, "/docs/notes.txt", &[("Range", "bytes=-3")]);
+        assert_eq!(body(response), b"789");
+
+        let response = serve(&files, "/docs/notes.txt", &[("Range", "bytes=20-")]);
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers().get("Content-Range"), Some("bytes */10"));
+
+        let response = serve(
+            &files,
+            "/docs/notes.txt",
+            &[("Range", "bytes=0-1"), ("If-Range", "\"stale\"")],
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response), b"0123456789");
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("bytes=0-0", 10), ByteRange::Satisfiable(0, 0));
+        assert_eq!(parse_range("bytes=5-", 10), ByteRange::Satisfiable(5, 9));
+        assert_eq!(parse_range("bytes=5-100", 10), ByteRange::Satisfiable(5, 9));
+        assert_eq!(parse_range("bytes=-100", 10), ByteRange::Satisfiable(0, 9));
+        assert_eq!(parse_range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-2", 10), ByteRange::Ignored);
+        assert_eq!(parse_range("bytes=0-1,3-4", 10), ByteRange::Ignored);
+        assert_eq!(parse_range("items=0-1", 10), ByteRange::Ignored);
+        assert_eq!(parse_range("bytes=a-b", 10), ByteRange::Ignored);
+        assert_eq!(parse_range("bytes=+1-2", 10), ByteRange::Ignored);
+    }
+
+    #[test]
+    fn guesses_media_types() {
+        assert_eq!(mime_type(Path::new("a/b.CSS")), "text/css; charset=utf-8");
+        assert_eq!(mime_type(Path::new("logo.png")), "image/png");
+        assert_eq!(mime_type(Path::new("Makefile")), "application/octet-stream");
+    }
+}
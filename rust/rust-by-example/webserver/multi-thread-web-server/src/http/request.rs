@@ -0,0 +1,849 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read},
+    mem,
+};
+
+use super::{percent_decode, Headers, Method, StatusCode, Version};
+
+/// How long the request line and headers may be unless [`RequestParser::max_head_size`] says.
+const DEFAULT_MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// How long a body may be unless [`RequestParser::max_body_size`] says.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// How long the size line of a chunk may be, extensions included.
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+/// A request read from a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: Method,
+    /// The path of the request target as sent, still percent-encoded.
+    pub path: String,
+    /// What followed the `?` in the request target, if anything.
+    pub query: Option<String>,
+    pub version: Version,
+    pub headers: Headers,
+    /// The body, with any chunked encoding removed.
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// The decoded value of the first query parameter called `name`.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(key, _)| decode_query(key).as_deref() == Some(name))
+            .and_then(|(_, value)| decode_query(value))
+    }
+}
+
+fn decode_query(component: &str) -> Option<String> {
+    percent_decode(&component.replace('+', " "))
+}
+
+/// Why a request could not be parsed.
+///
+/// The connection cannot be trusted to be in sync after any of these, so the server should
+/// answer with [`ParseError::status`] and close it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The request line is not `METHOD target HTTP/x.y`.
+    BadRequestLine,
+    /// The method is not one this server knows.
+    UnknownMethod(String),
+    /// The request is for an HTTP version other than 1.0 or 1.1.
+    UnsupportedVersion(String),
+    /// A header line is not `name: value`, or the head is not valid UTF-8.
+    BadHeader,
+    /// `Content-Length` is not a number, or appears with different values.
+    BadContentLength,
+    /// A `Transfer-Encoding` other than `chunked`.
+    UnsupportedTransferEncoding(String),
+    /// Both `Content-Length` and `Transfer-Encoding` were sent.
+    AmbiguousLength,
+    /// A chunk of a chunked body is malformed.
+    BadChunk,
+    /// The request line and headers are longer than [`RequestParser::max_head_size`].
+    HeadTooLarge,
+    /// The body is longer than [`RequestParser::max_body_size`].
+    BodyTooLarge,
+}
+
+impl ParseError {
+    /// The status to answer the request with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::UnknownMethod(_) | ParseError::UnsupportedTransferEncoding(_) => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            ParseError::UnsupportedVersion(_) => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::HeadTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ParseError::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::BadRequestLine => write!(f, "malformed request line"),
+            ParseError::UnknownMethod(method) => write!(f, "unknown method {:?}", method),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported HTTP version {:?}", version)
+            }
+            ParseError::BadHeader => write!(f, "malformed header"),
+            ParseError::BadContentLength => write!(f, "invalid Content-Length"),
+            ParseError::UnsupportedTransferEncoding(encoding) => {
+                write!(f, "unsupported Transfer-Encoding {:?}", encoding)
+            }
+            ParseError::AmbiguousLength => {
+                write!(f, "both Content-Length and Transfer-Encoding were sent")
+            }
+            ParseError::BadChunk => write!(f, "malformed chunk"),
+            ParseError::HeadTooLarge => write!(f, "request head too large"),
+            ParseError::BodyTooLarge => write!(f, "request body too large"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Why [`RequestParser::read_from`] did not return a request.
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "failed to read request: {}", err),
+            ReadError::Parse(err) => write!(f, "bad request: {}", err),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            ReadError::Parse(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(err: ParseError) -> ReadError {
+        ReadError::Parse(err)
+    }
+}
+
+/// Turns the bytes of a connection into requests, however they are split across reads.
+///
+/// Bytes after the end of a request stay buffered for the next one, so pipelined requests
+/// are parsed in turn.
+#[derive(Debug)]
+pub struct RequestParser {
+    buffer: Vec<u8>,
+    /// How far the buffer was searched for the end of the head.
+    scanned: usize,
+    max_head_size: usize,
+    max_body_size: usize,
+    state: State,
+}
+
+impl Default for RequestParser {
+    fn default() -> RequestParser {
+        RequestParser {
+            buffer: Vec::new(),
+            scanned: 0,
+            max_head_size: DEFAULT_MAX_HEAD_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            state: State::Head,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Head,
+    Body {
+        request: Request,
+        length: usize,
+    },
+    Chunked {
+        request: Request,
+        chunk: Chunk,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Chunk {
+    Size,
+    Data(usize),
+    Trailers,
+}
+
+impl RequestParser {
+    pub fn new() -> RequestParser {
+        RequestParser::default()
+    }
+
+    /// Limits the request line and headers to `size` bytes, 8 KiB unless set.
+    ///
+    /// A longer head fails with [`ParseError::HeadTooLarge`] as soon as that many bytes
+    /// have arrived without its end, rather than being buffered for as long as it takes.
+    pub fn max_head_size(mut self, size: usize) -> RequestParser {
+        self.max_head_size = size;
+        self
+    }
+
+    /// Limits the body to `size` bytes, 1 MiB unless set.
+    ///
+    /// A longer body fails with [`ParseError::BodyTooLarge`] as soon as its length or the size
+    /// of a chunk says it would be longer, before any more of it is buffered.
+    pub fn max_body_size(mut self, size: usize) -> RequestParser {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Appends bytes read from the connection.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Whether no bytes of a request are waiting to be parsed.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty() && matches!(self.state, State::Head)
+    }
+
+    /// Whether the head of the request being parsed is complete and its body is awaited.
+    pub fn awaits_body(&self) -> bool {
+        !matches!(self.state, State::Head)
+    }
+
+    /// Returns the next request once all of it has been fed, or `None` until then.
+    pub fn parse(&mut self) -> Result<Option<Request>, ParseError> {
+        loop {
+            match &mut self.state {
+                State::Head => {
+                    let Some(head) = self.take_head() else {
+                        if self.buffer.len() > self.max_head_size {
+                            return Err(ParseError::HeadTooLarge);
+                        }
+                        return Ok(None);
+                    };
+                    if head.len() > self.max_head_size {
+                        return Err(ParseError::HeadTooLarge);
+                    }
+                    let request = parse_head(&head)?;
+
+                    match body_length(&request.headers)? {
+                        BodyLength::Fixed(0) => return Ok(Some(request)),
+                        BodyLength::Fixed(length) if length > self.max_body_size => {
+                            return Err(ParseError::BodyTooLarge);
+                        }
+                        BodyLength::Fixed(length) => self.state = State::Body { request, length },
+                        BodyLength::Chunked => {
+                            self.state = State::Chunked {
+                                request,
+                                chunk: Chunk::Size,
+                            }
+                        }
+                    }
+                }
+                State::Body { length, .. } => {
+                    if self.buffer.len() < *length {
+                        return Ok(None);
+                    }
+
+                    let body = self.buffer.drain(..*length).collect();
+                    let mut request = self.finish();
+                    request.body = body;
+                    return Ok(Some(request));
+                }
+                State::Chunked { request, chunk } => match *chunk {
+                    Chunk::Size => {
+                        let Some(line) = take_line(&mut self.buffer) else {
+                            if self.buffer.len() > MAX_CHUNK_SIZE_LINE {
+                                return Err(ParseError::BadChunk);
+                            }
+                            return Ok(None);
+                        };
+                        let room = self.max_body_size - request.body.len();
+                        let size = parse_chunk_size(&line, room)?;
+                        *chunk = if size == 0 {
+                            Chunk::Trailers
+                        } else {
+                            Chunk::Data(size)
+                        };
+                    }
+                    Chunk::Data(size) => {
+                        // the size is the client's, anything up to `usize::MAX`
+                        let end = size.checked_add(2).ok_or(ParseError::BadChunk)?;
+                        if self.buffer.len() < end {
+                            return Ok(None);
+                        }
+                        if &self.buffer[size..end] != b"\r\n" {
+                            return Err(ParseError::BadChunk);
+                        }
+
+                        request.body.extend(self.buffer.drain(..size));
+                        self.buffer.drain(..2);
+                        *chunk = Chunk::Size;
+                    }
+                    Chunk::Trailers => {
+                        // trailer fields are read past but not kept, each is held to the limit
+                        // of the head they could have been sent in
+                        let Some(line) = take_line(&mut self.buffer) else {
+                            if self.buffer.len() > self.max_head_size {
+                                return Err(ParseError::HeadTooLarge);
+                            }
+                            return Ok(None);
+                        };
+                        if line.is_empty() {
+                            return Ok(Some(self.finish()));
+                        }
+                        if !line.contains(&b':') {
+                            return Err(ParseError::BadChunk);
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// Reads from `reader` until a whole request has arrived.
+    ///
+    /// Returns `None` if the connection was closed before any byte of a request, and an
+    /// [`io::ErrorKind::UnexpectedEof`] error if it was closed in the middle of one.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> Result<Option<Request>, ReadError> {
+        let mut chunk = [0; 4096];
+
+        loop {
+            if let Some(request) = self.parse()? {
+                return Ok(Some(request));
+            }
+
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                if self.is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.feed(&chunk[..read]);
+        }
+    }
+
+    /// Removes the head of the next request from the buffer, once all of it is there.
+    fn take_head(&mut self) -> Option<Vec<u8>> {
+        // empty lines before a request line are ignored
+        let blank = self
+            .buffer
+            .iter()
+            .take_while(|&&byte| byte == b'\r' || byte == b'\n');
+        let blank = blank.count();
+        if blank > 0 {
+            self.buffer.drain(..blank);
+            self.scanned = 0;
+        }
+
+        let start = self.scanned.saturating_sub(3);
+        let Some(end) = find(&self.buffer[start..], b"\r\n\r\n") else {
+            self.scanned = self.buffer.len();
+            return None;
+        };
+
+        let head = self.buffer.drain(..start + end + 4).collect();
+        self.scanned = 0;
+        Some(head)
+    }
+
+    fn finish(&mut self) -> Request {
+        match mem::take(&mut self.state) {
+            State::Body { request, .. } | State::Chunked { request, .. } => request,
+            State::Head => unreachable!("a request is being read"),
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Removes a CRLF terminated line from the front of `buffer`, without the CRLF.
+fn take_line(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let end = find(buffer, b"\r\n")?;
+    let mut line: Vec<u8> = buffer.drain(..end + 2).collect();
+    line.truncate(end);
+    Some(line)
+}
+
+fn parse_head(head: &[u8]) -> Result<Request, ParseError> {
+    let head = std::str::from_utf8(head).map_err(|_| ParseError::BadHeader)?;
+    let mut lines = head.trim_end_matches("\r\n").split("\r\n");
+
+    let request_line = lines.next().ok_or(ParseError::BadRequestLine)?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::BadRequestLine);
+    };
+
+    let method =
+        Method::parse(method).ok_or_else(|| ParseError::UnknownMethod(method.to_string()))?;
+    if !(target.starts_with('/') || (target == "*" && method == Method::Options))
+        || target.chars().any(|c| c.is_ascii_control())
+    {
+        return Err(ParseError::BadRequestLine);
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        _ if is_http_version(version) => {
+            return Err(ParseError::UnsupportedVersion(version.to_string()))
+        }
+        _ => return Err(ParseError::BadRequestLine),
+    };
+
+    let mut headers = Headers::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(ParseError::BadHeader)?;
+        // no whitespace is allowed around the name, which also rules out line folding
+        if name.is_empty() || !name.bytes().all(is_token) {
+            return Err(ParseError::BadHeader);
+        }
+        let value = value.trim_matches(|c| c == ' ' || c == '\t');
+        if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+            return Err(ParseError::BadHeader);
+        }
+        headers.append(name, value);
+    }
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        version,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+fn is_http_version(version: &str) -> bool {
+    let Some(number) = version.strip_prefix("HTTP/") else {
+        return false;
+    };
+    let mut digits = number.split('.');
+    matches!(
+        (digits.next(), digits.next(), digits.next()),
+        (Some(major), Some(minor), None)
+            if [major, minor]
+                .iter()
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    )
+}
+
+/// Whether `byte` may appear in a header name.
+fn is_token(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+enum BodyLength {
+    Fixed(usize),
+    Chunked,
+}
+
+fn body_length(headers: &Headers) -> Result<BodyLength, ParseError> {
+    let encodings: Vec<&str> = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|encoding| !encoding.is_empty())
+        .collect();
+    let has_length = headers.contains("Content-Length");
+
+    if !encodings.is_empty() {
+        if has_length {
+            return Err(ParseError::AmbiguousLength);
+        }
+        return match encodings.as_slice() {
+            [encoding] if encoding.eq_ignore_ascii_case("chunked") => Ok(BodyLength::Chunked),
+            [.., last] if !last.eq_ignore_ascii_case("chunked") => {
+                Err(ParseError::UnsupportedTransferEncoding(last.to_string()))
+            }
+            _ => Err(ParseError::UnsupportedTransferEncoding(
+                encodings.join(", "),
+            )),
+        };
+    }
+
+    let mut length = None;
+    for value in headers
+        .get_all("Content-Length")
+        .flat_map(|value| value.split(','))
+    {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::BadContentLength);
+        }
+        let value: usize = value.parse().map_err(|_| ParseError::BadContentLength)?;
+        if length.is_some_and(|length| length != value) {
+            return Err(ParseError::BadContentLength);
+        }
+        length = Some(value);
+    }
+
+    Ok(BodyLength::Fixed(length.unwrap_or(0)))
+}
+
+/// Parses the size line of a chunk, which may take up to `room` more bytes of body.
+fn parse_chunk_size(line: &[u8], room: usize) -> Result<usize, ParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| ParseError::BadChunk)?;
+    // chunk extensions are ignored
+    let size = line.split(';').next().unwrap_or_default().trim_end();
+
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::BadChunk);
+    }
+    let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::BadChunk)?;
+    if size > room {
+        return Err(ParseError::BodyTooLarge);
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &[u8]) -> Result<Option<Request>, ParseError> {
+        let mut parser = RequestParser::new();
+        parser.feed(input);
+        parser.parse()
+    }
+
+    fn parse_error(input: &[u8]) -> ParseError {
+        parse(input).expect_err("malformed request was accepted")
+    }
+
+    #[test]
+    fn parses_a_request_with_query_and_headers() {
+        let request = parse(
+            b"GET /search?q=rust+book&page=2 HTTP/1.1\r\nHost: localhost\r\nX-Empty:\r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.query.as_deref(), Some("q=rust+book&page=2"));
+        assert_eq!(request.query_param("q").as_deref(), Some("rust book"));
+        assert_eq!(request.query_param("page").as_deref(), Some("2"));
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("X-Empty"), Some(""));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn parses_a_request_fed_one_byte_at_a_time() {
+        let input = b"POST /todos HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+        let mut parser = RequestParser::new();
+
+        for (i, byte) in input.iter().enumerate() {
+            parser.feed(&[*byte]);
+            let request = parser.parse().unwrap();
+            assert_eq!(request.is_some(), i == input.len() - 1);
+            if let Some(request) = request {
+                assert_eq!(request.body, b"hello");
+            }
+        }
+        assert!(parser.is_empty());
+    }
+
+    #[test]
+    fn parses_a_chunked_body_with_extensions_and_trailers() {
+        let request = parse(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+              4;ext=1\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(request.body, b"Wikipedia");
+    }
+
+    #[test]
+    fn keeps_pipelined_requests_apart() {
+        let mut parser = RequestParser::new();
+        parser.feed(b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nokGET /c HTTP/1.0\r\n");
+
+        assert_eq!(parser.parse().unwrap().unwrap().path, "/a");
+        assert_eq!(parser.parse().unwrap().unwrap().body, b"ok");
+        assert_eq!(parser.parse().unwrap(), None);
+        assert!(!parser.is_empty());
+
+        parser.feed(b"\r\n");
+        let request = parser.parse().unwrap().unwrap();
+        assert_eq!(
+            (request.path.as_str(), request.version),
+            ("/c", Version::Http10)
+        );
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_request() {
+        assert_eq!(parse(b"GET / HTTP/1.1\r\nHost: x\r\n"), Ok(None));
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"),
+            Ok(None)
+        );
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nab"),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_request_lines() {
+        for input in [
+            &b"GET\r\n\r\n"[..],
+            b"GET / \r\n\r\n",
+            b"GET  / HTTP/1.1\r\n\r\n",
+            b"GET / HTTP/1.1 extra\r\n\r\n",
+            b"GET index.html HTTP/1.1\r\n\r\n",
+            b"GET * HTTP/1.1\r\n\r\n",
+            b"GET / FTP/1.1\r\n\r\n",
+            b"GET / http/1.1\r\n\r\n",
+            b"GET /\x7f HTTP/1.1\r\n\r\n",
+        ] {
+            assert_eq!(
+                parse_error(input),
+                ParseError::BadRequestLine,
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_methods_and_versions() {
+        let err = parse_error(b"BREW / HTTP/1.1\r\n\r\n");
+        assert_eq!(err, ParseError::UnknownMethod("BREW".into()));
+        assert_eq!(err.status(), StatusCode::NOT_IMPLEMENTED);
+
+        assert_eq!(
+            parse_error(b"get / HTTP/1.1\r\n\r\n"),
+            ParseError::UnknownMethod("get".into())
+        );
+
+        let err = parse_error(b"GET / HTTP/2.0\r\n\r\n");
+        assert_eq!(err, ParseError::UnsupportedVersion("HTTP/2.0".into()));
+        assert_eq!(err.status(), StatusCode::HTTP_VERSION_NOT_SUPPORTED);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for input in [
+            &b"GET / HTTP/1.1\r\nHost localhost\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nHost : localhost\r\n\r\n",
+            b"GET / HTTP/1.1\r\n: localhost\r\n\r\n",
+            b"GET / HTTP/1.1\r\nX-Folded: a\r\n b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nX-Bad: a\x00b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nX-Bytes: \xff\r\n\r\n",
+        ] {
+            let err = parse_error(input);
+            assert_eq!(err, ParseError::BadHeader, "{:?}", input);
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_content_lengths() {
+        for value in [
+            "-1",
+            "+5",
+            "five",
+            "0x10",
+            "",
+            "1 2",
+            "3, 4",
+            "99999999999999999999999",
+        ] {
+            let input = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", value);
+            assert_eq!(
+                parse_error(input.as_bytes()),
+                ParseError::BadContentLength,
+                "{:?}",
+                value
+            );
+        }
+
+        assert_eq!(
+            parse_error(b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n"),
+            ParseError::BadContentLength
+        );
+        assert_eq!(
+            parse(b"POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap()
+                .unwrap()
+                .body,
+            b"ok"
+        );
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_unsupported_transfer_encodings() {
+        assert_eq!(
+            parse_error(
+                b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n"
+            ),
+            ParseError::AmbiguousLength
+        );
+
+        let err = parse_error(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n");
+        assert_eq!(err, ParseError::UnsupportedTransferEncoding("gzip".into()));
+        assert_eq!(err.status(), StatusCode::NOT_IMPLEMENTED);
+
+        assert!(matches!(
+            parse_error(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+            ParseError::UnsupportedTransferEncoding(_)
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_chunks() {
+        for body in [
+            &b"zz\r\n"[..],
+            b"\r\n",
+            b"-1\r\n",
+            b"3\r\nabcd\r\n",
+            b"3\r\nabc",
+            b"fffffffffffffffffffff\r\n",
+            b"ffffffffffffffff\r\n",
+            b"0\r\nnot a trailer\r\n",
+        ] {
+            // without a body limit, so sizes up to `usize::MAX` get through to the data
+            let mut parser = RequestParser::new().max_body_size(usize::MAX);
+            parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n");
+            parser.feed(body);
+            parser.feed(b"XX\r\n\r\n");
+            assert_eq!(parser.parse(), Err(ParseError::BadChunk), "{:?}", body);
+        }
+
+        let mut parser = RequestParser::new();
+        parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1;");
+        parser.feed(&[b'x'; MAX_CHUNK_SIZE_LINE]);
+        assert_eq!(parser.parse(), Err(ParseError::BadChunk));
+    }
+
+    #[test]
+    fn rejects_bodies_over_the_size_limit() {
+        let head = |length: &str| format!("POST / HTTP/1.1\r\n{}\r\n\r\n", length);
+
+        let mut parser = RequestParser::new().max_body_size(4);
+        parser.feed(head("Content-Length: 4").as_bytes());
+        parser.feed(b"abcd");
+        assert_eq!(parser.parse().unwrap().unwrap().body, b"abcd");
+
+        // fails on the length alone, before the body arrives
+        parser.feed(head("Content-Length: 5").as_bytes());
+        assert_eq!(parser.parse(), Err(ParseError::BodyTooLarge));
+        assert_eq!(
+            ParseError::BodyTooLarge.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        // chunks count together
+        let mut parser = RequestParser::new().max_body_size(4);
+        parser.feed(head("Transfer-Encoding: chunked").as_bytes());
+        parser.feed(b"3\r\nabc\r\n2\r\n");
+        assert_eq!(parser.parse(), Err(ParseError::BodyTooLarge));
+
+        let mut parser = RequestParser::new();
+        parser.feed(head("Transfer-Encoding: chunked").as_bytes());
+        parser.feed(b"ffffffffffffffff\r\n");
+        assert_eq!(parser.parse(), Err(ParseError::BodyTooLarge));
+    }
+
+    #[test]
+    fn rejects_trailers_over_the_head_size_limit() {
+        let mut parser = RequestParser::new().max_head_size(64);
+        parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nX-Trailer: ");
+        parser.feed(&[b'a'; 64]);
+        assert_eq!(parser.parse(), Err(ParseError::HeadTooLarge));
+    }
+
+    #[test]
+    fn rejects_heads_over_the_size_limit() {
+        let mut parser = RequestParser::new().max_head_size(32);
+        parser.feed(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(parser.parse().unwrap().is_some());
+
+        // fails before the end of the head arrives
+        parser.feed(b"GET / HTTP/1.1\r\nCookie: ");
+        parser.feed(&[b'a'; 32]);
+        assert_eq!(parser.parse(), Err(ParseError::HeadTooLarge));
+        assert_eq!(
+            ParseError::HeadTooLarge.status(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+
+        let mut parser = RequestParser::new().max_head_size(32);
+        parser.feed(b"GET / HTTP/1.1\r\nCookie: aaaaaaaaaaaaaaaa\r\n\r\n");
+        assert_eq!(parser.parse(), Err(ParseError::HeadTooLarge));
+    }
+
+    #[test]
+    fn reads_requests_from_a_reader() {
+        let mut input: &[u8] = b"GET / HTTP/1.1\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+        let mut parser = RequestParser::new();
+
+        assert_eq!(parser.read_from(&mut input).unwrap().unwrap().path, "/");
+        assert_eq!(parser.read_from(&mut input).unwrap().unwrap().path, "/next");
+        assert!(parser.read_from(&mut input).unwrap().is_none());
+
+        let mut truncated: &[u8] = b"GET / HTTP/1.1\r\nHost:";
+        match RequestParser::new().read_from(&mut truncated) {
+            Err(ReadError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("/a%20b%2Fc").as_deref(), Some("/a b/c"));
+        assert_eq!(percent_decode("%E2%9C%93").as_deref(), Some("✓"));
+        assert_eq!(percent_decode("%"), None);
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+}
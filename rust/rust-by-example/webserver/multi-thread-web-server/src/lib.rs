@@ -1,56 +1,633 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt, iter, mem,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
+use crossbeam_deque::{self as deque, Injector, Steal, Stealer};
+
+mod builder;
+pub mod connection;
+pub mod files;
+pub mod http;
+pub mod router;
+mod scope;
+mod task;
+
+pub use builder::{Builder, RejectionPolicy};
+pub use scope::Scope;
+pub use task::{JoinError, TaskHandle};
+
+/// A pool of worker threads that schedules jobs by work stealing.
+///
+/// Jobs queued from outside the pool go to a shared injector queue, jobs queued by a running
+/// job go to the local deque of its worker. A worker takes jobs from its own deque first, then
+/// grabs a batch from the injector, and only then steals from the other workers.
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
+    workers: Mutex<Vec<Worker>>,
+    shared: Arc<Shared>,
+    policy: RejectionPolicy,
+}
+
+/// A snapshot of what the pool has been doing, see [`ThreadPool::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Workers running a job.
+    pub active: usize,
+    /// Workers waiting for a job.
+    pub idle: usize,
+    /// Jobs waiting for a worker.
+    pub queued: usize,
+    /// Jobs that ran to the end.
+    pub completed: usize,
+    /// Jobs that panicked. The worker running them recovered and kept going.
+    pub panicked: usize,
+}
+
+/// Why [`ThreadPool::execute`] did not accept a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecuteError {
+    /// The queue is full and the pool uses [`RejectionPolicy::Reject`].
+    QueueFull,
+    /// The pool was shut down with [`ThreadPool::shutdown`].
+    ShutDown,
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecuteError::QueueFull => write!(f, "job queue is full"),
+            ExecuteError::ShutDown => write!(f, "thread pool is shut down"),
+        }
+    }
+}
+
+impl Error for ExecuteError {}
+
+/// The jobs [`ThreadPool::shutdown`] gave up on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Unfinished {
+    /// Jobs still running. Their workers are left to finish them in the background.
+    pub running: usize,
+    /// Jobs dropped from the queue without running.
+    pub queued: usize,
+}
+
+impl Unfinished {
+    /// Whether every job finished in time.
+    pub fn is_empty(&self) -> bool {
+        self.running == 0 && self.queued == 0
+    }
+}
+
+/// How many times an idle worker looks for a job before it goes to sleep.
+const SEARCH_ROUNDS: usize = 16;
+
+thread_local! {
+    /// The deque of the pool worker running on this thread, if any.
+    static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+struct Local {
+    pool: *const Shared,
+    jobs: deque::Worker<Job>,
+    /// Whether the worker is running a job, rather than counted as searching.
+    busy: Cell<bool>,
+}
+
+/// State shared by the pool and its workers.
+///
+/// Queuing and taking jobs only touches the deques and the counters. The `sleep` mutex is
+/// taken to park idle workers and blocked callers, and to wake them up.
+struct Shared {
+    injector: Injector<Job>,
+    stealers: RwLock<Vec<(usize, Stealer<Job>)>>,
+    /// Wake-ups handed to idle workers that have not consumed them yet.
+    sleep: Mutex<usize>,
+    /// Signalled when a job is queued or the pool shuts down.
+    job_queued: Condvar,
+    /// Signalled when a job is taken off the queue while callers wait for room.
+    job_taken: Condvar,
+    /// Signalled when a worker stops after the pool was shut down.
+    terminated: Condvar,
+    /// Jobs in the injector and all deques, including places reserved for jobs being queued.
+    queued: AtomicUsize,
+    /// Live workers, so the pool never grows past its maximum.
+    threads: AtomicUsize,
+    idle: AtomicUsize,
+    /// Workers between jobs that are looking for one and will take the next job queued.
+    searching: AtomicUsize,
+    blocked: AtomicUsize,
+    next_id: AtomicUsize,
+    shutdown: AtomicBool,
+    capacity: Option<usize>,
+    core_threads: usize,
+    max_threads: usize,
+    idle_timeout: Duration,
+    completed: AtomicUsize,
+    panicked: AtomicUsize,
+}
+
+impl Shared {
+    // jobs never run under the lock, so it is never poisoned by a panicking job
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.sleep.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn run(&self, job: Job) -> bool {
+        let completed = panic::catch_unwind(AssertUnwindSafe(|| job.call_box())).is_ok();
+        if completed {
+            self.completed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.panicked.fetch_add(1, Ordering::Relaxed);
+        }
+        completed
+    }
+
+    /// Claims a place in the queue, failing if it is full.
+    fn reserve(&self) -> bool {
+        let capacity = self.capacity.unwrap_or(usize::MAX);
+        self.queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < capacity).then_some(queued + 1)
+            })
+            .is_ok()
+    }
+
+    /// Whether the pool was shut down, checked under the lock workers check it under before
+    /// they terminate: a job whose place was reserved before is seen by the workers, which
+    /// stay to run it, or the shutdown is seen here.
+    fn is_shut_down(&self) -> bool {
+        let _sleep = self.lock();
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Whether the calling thread is a worker of this pool.
+    fn is_worker(&self) -> bool {
+        LOCAL.with(|local| {
+            local
+                .borrow()
+                .as_ref()
+                .is_some_and(|local| ptr::eq(local.pool, self))
+        })
+    }
+
+    /// Runs a queued job on the calling worker of this pool, if there is one.
+    fn help(&self) -> bool {
+        let job = LOCAL.with(|local| match &*local.borrow() {
+            Some(local) if ptr::eq(local.pool, self) => self.find(&local.jobs),
+            _ => None,
+        });
+
+        job.map(|job| {
+            self.run(job);
+            // the job we helped with may have handed our worker back, but we are still busy
+            self.started();
+        })
+        .is_some()
+    }
+
+    /// Counts the calling worker of this pool as running a job instead of searching.
+    fn started(&self) {
+        LOCAL.with(|local| match &*local.borrow() {
+            Some(local) if ptr::eq(local.pool, self) && !local.busy.replace(true) => {
+                self.searching.fetch_sub(1, Ordering::SeqCst);
+            }
+            _ => {}
+        });
+    }
+
+    /// Counts the calling worker of this pool as searching again.
+    ///
+    /// Called once a job is done, and by [`ThreadPool::submit`] jobs right before they hand
+    /// over their result: the caller may queue its next job before the worker is back.
+    fn finished(&self) {
+        LOCAL.with(|local| match &*local.borrow() {
+            Some(local) if ptr::eq(local.pool, self) && local.busy.replace(false) => {
+                self.searching.fetch_add(1, Ordering::SeqCst);
+            }
+            _ => {}
+        });
+    }
+
+    /// Queues a job whose place was reserved and wakes a worker for it, returning whether
+    /// there was an idle worker to wake.
+    fn push(&self, job: Job) -> bool {
+        let job = LOCAL.with(|local| match &*local.borrow() {
+            Some(local) if ptr::eq(local.pool, self) => {
+                local.jobs.push(job);
+                None
+            }
+            _ => Some(job),
+        });
+        if let Some(job) = job {
+            self.injector.push(job);
+        }
+
+        if self.idle.load(Ordering::SeqCst) > 0 {
+            let mut woken = self.lock();
+            // claim the worker we wake, so the jobs pushed until it runs leave the others asleep
+            if self.idle.load(Ordering::SeqCst) > 0 {
+                self.idle.fetch_sub(1, Ordering::SeqCst);
+                *woken += 1;
+                self.job_queued.notify_one();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn steal(&self) -> Steal<Job> {
+        let stealers = self.stealers.read().unwrap_or_else(PoisonError::into_inner);
+        stealers
+            .iter()
+            .map(|(_, stealer)| stealer.steal())
+            .collect()
+    }
+
+    /// Takes the next job for the worker owning `local`.
+    fn find(&self, local: &deque::Worker<Job>) -> Option<Job> {
+        let job = local.pop().or_else(|| {
+            iter::repeat_with(|| {
+                self.injector
+                    .steal_batch_and_pop(local)
+                    .or_else(|| self.steal())
+            })
+            .find(|steal| !steal.is_retry())
+            .and_then(Steal::success)
+        })?;
+
+        self.taken();
+        Some(job)
+    }
+
+    /// Looks for a job a few times before the worker goes to sleep, giving threads queuing
+    /// jobs a chance to run, as parking and waking a worker costs more than a few tries.
+    fn search(&self, local: &deque::Worker<Job>) -> Option<Job> {
+        (0..SEARCH_ROUNDS).find_map(|_| {
+            self.find(local).or_else(|| {
+                thread::yield_now();
+                None
+            })
+        })
+    }
+
+    /// Drops the oldest job that can be found, freeing its place in the queue.
+    fn drop_oldest(&self) -> bool {
+        let dropped = iter::repeat_with(|| self.injector.steal().or_else(|| self.steal()))
+            .find(|steal| !steal.is_retry())
+            .and_then(Steal::success)
+            .is_some();
+
+        if dropped {
+            self.taken();
+        }
+        dropped
+    }
+
+    fn taken(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        if self.blocked.load(Ordering::SeqCst) > 0 {
+            let _sleep = self.lock();
+            self.job_taken.notify_all();
+        }
+    }
+
+    /// Blocks until the queue might have room.
+    fn wait_for_room(&self) {
+        let capacity = self.capacity.unwrap_or(usize::MAX);
+        let sleep = self.lock();
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+        // checked after announcing ourselves blocked, so a job taken meanwhile wakes us
+        if self.queued.load(Ordering::SeqCst) >= capacity {
+            let _sleep = self
+                .job_taken
+                .wait(sleep)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Parks an idle worker until there may be a job for it.
+    fn wait(&self, local: &deque::Worker<Job>) -> Wake {
+        let sleep = self.lock();
+        self.idle.fetch_add(1, Ordering::SeqCst);
+        // only stop searching once idle, so jobs queued meanwhile see one or the other
+        self.searching.fetch_sub(1, Ordering::SeqCst);
+
+        // checked after announcing ourselves idle, so a job queued meanwhile wakes us
+        if self.queued.load(Ordering::SeqCst) > 0 {
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+            self.searching.fetch_add(1, Ordering::SeqCst);
+            drop(sleep);
+            thread::yield_now();
+            return Wake::Retry;
+        }
+        if self.shutdown.load(Ordering::SeqCst) {
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+            self.threads.fetch_sub(1, Ordering::SeqCst);
+            self.terminated.notify_all();
+            return Wake::Terminate;
+        }
+
+        let (mut woken, wait) = self
+            .job_queued
+            .wait_timeout(sleep, self.idle_timeout)
+            .unwrap_or_else(PoisonError::into_inner);
+        if *woken > 0 {
+            // whoever woke us already took us off the idle count
+            *woken -= 1;
+        } else {
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+        }
+        drop(woken);
+
+        // workers beyond the core size retire once there is nothing left to do
+        let retire = wait.timed_out()
+            && local.is_empty()
+            && self.queued.load(Ordering::SeqCst) == 0
+            && self
+                .threads
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |threads| {
+                    (threads > self.core_threads).then(|| threads - 1)
+                })
+                .is_ok();
+
+        if retire {
+            Wake::Retire
+        } else {
+            self.searching.fetch_add(1, Ordering::SeqCst);
+            Wake::Retry
+        }
+    }
+
+    /// Reserves a worker id if no worker is idle, more jobs are waiting than workers are
+    /// searching, and the pool may grow.
+    ///
+    /// Called after queuing a job that woke no worker: one that was woken is no longer
+    /// counted as idle, but is on its way to take the job.
+    fn reserve_worker(&self) -> Option<usize> {
+        // searching first: a worker stops searching only after it is counted as idle
+        let searching = self.searching.load(Ordering::SeqCst);
+        if self.idle.load(Ordering::SeqCst) > 0 || self.queued.load(Ordering::SeqCst) <= searching {
+            return None;
+        }
+
+        self.threads
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |threads| {
+                (threads < self.max_threads).then_some(threads + 1)
+            })
+            .ok()?;
+        // the new worker starts out looking for the job
+        self.searching.fetch_add(1, Ordering::SeqCst);
+
+        Some(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 impl ThreadPool {
+    /// Creates a pool of `size` threads with an unbounded queue.
     pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
+        Builder::new().threads(size).build()
+    }
 
-        let (sender, receiver) = mpsc::channel();
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    fn from_builder(builder: Builder) -> ThreadPool {
+        let max_threads = builder.max_threads.max(builder.core_threads);
+        assert!(max_threads > 0);
+        assert!(builder.queue_capacity != Some(0));
+
+        let shared = Arc::new(Shared {
+            injector: Injector::new(),
+            stealers: RwLock::new(Vec::with_capacity(max_threads)),
+            sleep: Mutex::new(0),
+            job_queued: Condvar::new(),
+            job_taken: Condvar::new(),
+            terminated: Condvar::new(),
+            queued: AtomicUsize::new(0),
+            threads: AtomicUsize::new(builder.core_threads),
+            idle: AtomicUsize::new(0),
+            searching: AtomicUsize::new(builder.core_threads),
+            blocked: AtomicUsize::new(0),
+            next_id: AtomicUsize::new(builder.core_threads),
+            shutdown: AtomicBool::new(false),
+            capacity: builder.queue_capacity,
+            core_threads: builder.core_threads,
+            max_threads,
+            idle_timeout: builder.idle_timeout,
+            completed: AtomicUsize::new(0),
+            panicked: AtomicUsize::new(0),
+        });
 
-        let receiver = Arc::new(Mutex::new(receiver));
+        let mut workers = Vec::with_capacity(max_threads);
 
-        let mut workers = Vec::with_capacity(size);
+        for id in 0..builder.core_threads {
+            workers.push(Worker::new(id, Arc::clone(&shared)));
+        }
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+        ThreadPool {
+            workers: Mutex::new(workers),
+            shared,
+            policy: builder.rejection_policy,
         }
+    }
+
+    pub fn stats(&self) -> Stats {
+        // workers going to sleep or waking up update both counts under the lock
+        let sleep = self.shared.lock();
+        let threads = self.shared.threads.load(Ordering::SeqCst);
+        let idle = self.shared.idle.load(Ordering::SeqCst);
+        let searching = self.shared.searching.load(Ordering::SeqCst);
+        drop(sleep);
 
-        ThreadPool { workers, sender }
+        Stats {
+            // the rest are between jobs, looking for the next one
+            active: threads.saturating_sub(idle).saturating_sub(searching),
+            idle,
+            queued: self.shared.queued.load(Ordering::SeqCst),
+            completed: self.shared.completed.load(Ordering::Relaxed),
+            panicked: self.shared.panicked.load(Ordering::Relaxed),
+        }
     }
 
-    pub fn execute<F>(&self, f: F)
+    /// Queues `f` to run on the pool.
+    ///
+    /// Called from a job running on this pool, `f` goes to the local deque of that worker.
+    /// When the queue is full the pool's [`RejectionPolicy`] decides what happens, except
+    /// that such a job does not block or run `f` itself but queues it anyway; only
+    /// [`RejectionPolicy::Reject`] makes this return an error, as does a pool that was
+    /// [shut down](ThreadPool::shutdown).
+    pub fn execute<F>(&self, f: F) -> Result<(), ExecuteError>
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
+        if self.shared.shutdown.load(Ordering::SeqCst) {
+            return Err(ExecuteError::ShutDown);
+        }
+
+        let job: Job = Box::new(f);
 
-        self.sender.send(Message::NewJob(job)).unwrap();
+        while !self.shared.reserve() {
+            match self.policy {
+                // a worker waiting for room may be waiting for itself, and one running the job
+                // right away hides it from the pool, which would not grow for it: the job goes
+                // to the worker's deque even though the queue is full
+                RejectionPolicy::Block | RejectionPolicy::CallerRuns if self.shared.is_worker() => {
+                    self.shared.queued.fetch_add(1, Ordering::SeqCst);
+                    break;
+                }
+                RejectionPolicy::Block => self.shared.wait_for_room(),
+                RejectionPolicy::Reject => return Err(ExecuteError::QueueFull),
+                RejectionPolicy::DropOldest => {
+                    self.shared.drop_oldest();
+                }
+                RejectionPolicy::CallerRuns => {
+                    self.shared.run(job);
+                    return Ok(());
+                }
+            }
+        }
+
+        // the workers may have terminated since the check above, leaving no one to run the job
+        if self.shared.is_shut_down() {
+            self.shared.taken();
+            return Err(ExecuteError::ShutDown);
+        }
+
+        if !self.shared.push(job) {
+            if let Some(id) = self.shared.reserve_worker() {
+                self.spawn_worker(id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn spawn_worker(&self, id: usize) {
+        let mut workers = self.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        // forget the workers that retired
+        workers.retain(|worker| {
+            worker
+                .thread
+                .as_ref()
+                .is_some_and(|thread| !thread.is_finished())
+        });
+        workers.push(Worker::new(id, Arc::clone(&self.shared)));
+    }
+
+    /// Runs `f` on the pool and returns a handle to its result.
+    ///
+    /// A panic in `f` is caught and returned as [`JoinError::Panicked`] by the handle
+    /// instead of taking down the worker. A job dropped from a full queue returns
+    /// [`JoinError::Cancelled`].
+    pub fn submit<F, T>(&self, f: F) -> Result<TaskHandle<T>, ExecuteError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let shared = Arc::clone(&self.shared);
+
+        self.execute(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            shared.finished();
+            match result {
+                Ok(value) => {
+                    // the caller may have dropped the handle
+                    let _ = sender.send(Ok(value));
+                }
+                Err(payload) => {
+                    let _ = sender.send(Err(JoinError::panicked(payload.as_ref())));
+                    // let the worker count the panic too
+                    panic::resume_unwind(payload);
+                }
+            }
+        })?;
+
+        Ok(TaskHandle::new(receiver))
+    }
+
+    /// Stops accepting jobs and gives the workers until `timeout` to finish the queued ones.
+    ///
+    /// Jobs still queued at the deadline are dropped, so their [`TaskHandle`]s return
+    /// [`JoinError::Cancelled`]. Workers still running a job are detached rather than
+    /// joined; they stop once their job returns.
+    pub fn shutdown(&self, timeout: Duration) -> Unfinished {
+        let deadline = Instant::now() + timeout;
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+
+        let mut sleep = self.shared.lock();
+        self.shared.job_queued.notify_all();
+        while self.shared.threads.load(Ordering::SeqCst) > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            sleep = self
+                .shared
+                .terminated
+                .wait_timeout(sleep, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        drop(sleep);
+
+        let queued = iter::from_fn(|| self.shared.drop_oldest().then_some(())).count();
+        let running = self.shared.threads.load(Ordering::SeqCst);
+
+        let workers = mem::take(&mut *self.workers.lock().unwrap_or_else(PoisonError::into_inner));
+        for mut worker in workers {
+            let Some(thread) = worker.thread.take() else {
+                continue;
+            };
+
+            // a worker that was told to terminate may not have returned yet
+            if running > 0 && !thread.is_finished() {
+                println!("Worker {} is still busy; detaching.", worker.id);
+            } else if thread.join().is_err() {
+                println!("Worker {} had stopped after a panic.", worker.id);
+            }
+        }
+
+        Unfinished { running, queued }
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        println!("Sending terminate message to all workers.");
+        println!("Telling all workers to stop once the queue is empty.");
 
-        for _ in &mut self.workers {
-            self.sender.send(Message::Terminate).unwrap();
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        {
+            let _sleep = self.shared.lock();
+            self.shared.job_queued.notify_all();
         }
 
         println!("Shutting down all workers.");
 
-        for worker in &mut self.workers {
+        let workers = self
+            .workers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        for worker in workers {
             println!("Shutting down worker {}", worker.id);
 
-            // worker.thread.join().unwrap();
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                if thread.join().is_err() {
+                    println!("Worker {} had stopped after a panic.", worker.id);
+                }
             }
         }
     }
@@ -62,29 +639,72 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-
-            match message {
-                Message::NewJob(job) => {
-                    println!("Worker {} got a job; executing.", id);
-                    job.call_box();
-                }
-                Message::Terminate => {
-                    println!("Worker {} was told to terminate.", id);
-                    break;
+    fn new(id: usize, shared: Arc<Shared>) -> Worker {
+        let thread = thread::spawn(move || {
+            let jobs = deque::Worker::new_lifo();
+            shared
+                .stealers
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((id, jobs.stealer()));
+            LOCAL.with(|local| {
+                *local.borrow_mut() = Some(Local {
+                    pool: Arc::as_ptr(&shared),
+                    jobs,
+                    busy: Cell::new(false),
+                })
+            });
+
+            loop {
+                // released before the job runs, so the job can queue more jobs
+                let next = LOCAL.with(|local| {
+                    let local = local.borrow();
+                    let jobs = &local.as_ref().expect("set when the worker started").jobs;
+                    shared.search(jobs).ok_or_else(|| shared.wait(jobs))
+                });
+
+                match next {
+                    Ok(job) => {
+                        shared.started();
+                        if !shared.run(job) {
+                            println!("Worker {} recovered from a panicking job.", id);
+                        }
+                        shared.finished();
+                    }
+                    Err(Wake::Retry) => {}
+                    Err(Wake::Retire) => {
+                        println!("Worker {} was idle for too long; retiring.", id);
+                        break;
+                    }
+                    Err(Wake::Terminate) => {
+                        println!("Worker {} was told to terminate.", id);
+                        break;
+                    }
                 }
             }
+
+            shared
+                .stealers
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .retain(|(worker, _)| *worker != id);
+            LOCAL.with(|local| local.borrow_mut().take());
         });
 
         Worker {
-            id: id,
+            id,
             thread: Some(thread),
         }
     }
 }
 
+/// Why an idle worker woke up.
+enum Wake {
+    Retry,
+    Retire,
+    Terminate,
+}
+
 trait FnBox {
     fn call_box(self: Box<Self>);
 }
@@ -97,7 +717,277 @@ impl<F: FnOnce()> FnBox for F {
 
 type Job = Box<dyn FnBox + Send + 'static>;
 
-enum Message {
-    NewJob(Job),
-    Terminate,
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use super::*;
+
+    /// Polls `done` until it holds, failing the test after a few seconds.
+    pub(crate) fn wait_until(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Live workers, whether running a job, searching for one or idle.
+    fn threads(pool: &ThreadPool) -> usize {
+        pool.shared.threads.load(Ordering::SeqCst)
+    }
+
+    /// A pool with one worker and room for one queued job, whose worker stays busy until the
+    /// returned sender is dropped.
+    pub(crate) fn busy_pool(policy: RejectionPolicy) -> (ThreadPool, mpsc::Sender<()>) {
+        let pool = ThreadPool::builder()
+            .threads(1)
+            .queue_capacity(1)
+            .rejection_policy(policy)
+            .build();
+
+        let (release, released) = mpsc::channel::<()>();
+        let (started, running) = mpsc::channel();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            let _ = released.recv();
+        })
+        .unwrap();
+        running.recv().unwrap();
+
+        (pool, release)
+    }
+
+    #[test]
+    fn serial_jobs_do_not_grow_the_pool() {
+        let pool = ThreadPool::builder().core_threads(1).max_threads(8).build();
+
+        for i in 0..200 {
+            assert_eq!(pool.submit(move || i).unwrap().join(), Ok(i));
+        }
+
+        assert_eq!(threads(&pool), 1);
+    }
+
+    #[test]
+    fn a_burst_of_jobs_gets_a_worker_each() {
+        let pool = ThreadPool::builder().core_threads(1).max_threads(8).build();
+        let barrier = Arc::new(Barrier::new(5));
+
+        // only passes once all four jobs are running at the same time
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            pool.execute(move || {
+                barrier.wait();
+            })
+            .unwrap();
+        }
+        barrier.wait();
+
+        assert_eq!(threads(&pool), 4);
+    }
+
+    #[test]
+    fn only_workers_running_a_job_are_active() {
+        let pool = ThreadPool::new(3);
+        // fresh workers look for a job first
+        assert_eq!(pool.stats().active, 0);
+
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        let (started, running) = mpsc::channel();
+
+        for _ in 0..2 {
+            let started = started.clone();
+            let released = Arc::clone(&released);
+            pool.execute(move || {
+                started.send(()).unwrap();
+                let _ = released.lock().unwrap().recv();
+            })
+            .unwrap();
+        }
+        running.iter().take(2).for_each(drop);
+
+        // the third worker searches for a job before it goes to sleep, without becoming active
+        assert_eq!(pool.stats().active, 2);
+        wait_until(|| pool.stats().idle == 1);
+        assert_eq!(pool.stats().active, 2);
+
+        drop(release);
+        wait_until(|| pool.stats().idle == 3);
+        assert_eq!(pool.stats().active, 0);
+    }
+
+    #[test]
+    fn workers_survive_panicking_jobs() {
+        let pool = ThreadPool::new(1);
+        let worker = pool.submit(|| thread::current().id()).unwrap().join();
+
+        for _ in 0..3 {
+            let handle = pool.submit(|| panic!("job failed")).unwrap();
+            assert!(matches!(handle.join(), Err(JoinError::Panicked(_))));
+        }
+
+        // the same thread, so the panics did not take the worker down
+        assert_eq!(
+            pool.submit(|| thread::current().id()).unwrap().join(),
+            worker
+        );
+        assert_eq!(pool.stats().panicked, 3);
+        assert_eq!(threads(&pool), 1);
+    }
+
+    #[test]
+    fn grows_up_to_max_threads_and_retires_idle_workers() {
+        let pool = ThreadPool::builder()
+            .core_threads(1)
+            .max_threads(3)
+            .idle_timeout(Duration::from_millis(100))
+            .build();
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+
+        for _ in 0..5 {
+            let gate = Arc::clone(&gate);
+            pool.execute(move || drop(gate.read())).unwrap();
+        }
+        wait_until(|| pool.stats().active == 3);
+        let stats = pool.stats();
+        assert_eq!((stats.idle, stats.queued), (0, 2));
+        assert_eq!(threads(&pool), 3);
+
+        drop(closed);
+        wait_until(|| pool.stats().completed == 5);
+        wait_until(|| threads(&pool) == 1);
+        // the core worker stays
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(threads(&pool), 1);
+    }
+
+    #[test]
+    fn jobs_queued_by_a_job_go_to_its_local_deque() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let (sender, receiver) = mpsc::channel();
+
+        let inner = Arc::clone(&pool);
+        let worker = pool
+            .submit(move || {
+                for i in 0..10 {
+                    let sender = sender.clone();
+                    inner
+                        .execute(move || sender.send((i, thread::current().id())).unwrap())
+                        .unwrap();
+                }
+                assert!(inner.shared.injector.is_empty());
+                LOCAL.with(|local| assert_eq!(local.borrow().as_ref().unwrap().jobs.len(), 10));
+                thread::current().id()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let mut ran: Vec<_> = receiver.iter().collect();
+        ran.sort_by_key(|(i, _)| *i);
+        assert_eq!(
+            ran.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+        assert!(ran.iter().all(|(_, ran_on)| *ran_on == worker));
+        // a worker cannot join itself, so the last handle on the pool must be ours
+        wait_until(|| Arc::strong_count(&pool) == 1);
+    }
+
+    #[test]
+    fn jobs_queued_by_a_blocked_job_are_stolen() {
+        let pool = Arc::new(ThreadPool::new(2));
+        let (sender, receiver) = mpsc::channel();
+
+        let inner = Arc::clone(&pool);
+        let handle = pool
+            .submit(move || {
+                let worker = thread::current().id();
+                for _ in 0..4 {
+                    let sender = sender.clone();
+                    inner
+                        .execute(move || sender.send(thread::current().id()).unwrap())
+                        .unwrap();
+                }
+                // blocks this worker, so the other one has to steal the jobs to run them
+                receiver.iter().take(4).all(|thief| thief != worker)
+            })
+            .unwrap();
+
+        assert_eq!(handle.join(), Ok(true));
+        wait_until(|| Arc::strong_count(&pool) == 1);
+    }
+
+    #[test]
+    fn shutdown_finishes_queued_jobs_in_time() {
+        let pool = ThreadPool::new(2);
+        let handles: Vec<_> = (0..20).map(|i| pool.submit(move || i).unwrap()).collect();
+
+        assert_eq!(pool.shutdown(Duration::from_secs(5)), Unfinished::default());
+        assert_eq!(pool.stats().completed, 20);
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join(), Ok(i));
+        }
+
+        assert_eq!(pool.execute(|| {}), Err(ExecuteError::ShutDown));
+        assert!(matches!(pool.submit(|| 1), Err(ExecuteError::ShutDown)));
+    }
+
+    #[test]
+    fn jobs_accepted_while_shutting_down_still_run() {
+        for _ in 0..50 {
+            let pool = Arc::new(ThreadPool::new(2));
+            let ran = Arc::new(AtomicUsize::new(0));
+
+            let submitter = {
+                let pool = Arc::clone(&pool);
+                let ran = Arc::clone(&ran);
+                thread::spawn(move || {
+                    let mut accepted = 0;
+                    loop {
+                        let ran = Arc::clone(&ran);
+                        match pool.execute(move || {
+                            ran.fetch_add(1, Ordering::SeqCst);
+                        }) {
+                            Ok(()) => accepted += 1,
+                            Err(err) => {
+                                assert_eq!(err, ExecuteError::ShutDown);
+                                return accepted;
+                            }
+                        }
+                    }
+                })
+            };
+            wait_until(|| ran.load(Ordering::SeqCst) > 0);
+
+            assert_eq!(pool.shutdown(Duration::from_secs(5)), Unfinished::default());
+            let accepted = submitter.join().unwrap();
+            assert_eq!(ran.load(Ordering::SeqCst), accepted);
+        }
+    }
+
+    #[test]
+    fn shutdown_reports_the_jobs_it_gave_up_on() {
+        let (pool, release) = busy_pool(RejectionPolicy::Block);
+        let queued = pool.submit(|| 1).unwrap();
+
+        let unfinished = pool.shutdown(Duration::from_millis(50));
+        assert_eq!(
+            unfinished,
+            Unfinished {
+                running: 1,
+                queued: 1
+            }
+        );
+        assert!(!unfinished.is_empty());
+        assert_eq!(queued.join(), Err(JoinError::Cancelled));
+        assert_eq!(pool.execute(|| {}), Err(ExecuteError::ShutDown));
+
+        // the detached worker stops once its job returns
+        drop(release);
+        wait_until(|| pool.stats().active == 0);
+    }
 }
@@ -1,58 +1,171 @@
-use multi_thread_web_server::ThreadPool;
-use std::fs::File;
-use std::io::prelude::*;
+use multi_thread_web_server::connection::{
+    reject_connection, serve_connection, ConnectionConfig, ConnectionLimit,
+};
+use multi_thread_web_server::files::StaticFiles;
+use multi_thread_web_server::http::{Response, StatusCode};
+use multi_thread_web_server::router::Router;
+use multi_thread_web_server::{RejectionPolicy, ThreadPool};
 use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread;
+use std::time::Duration;
+use std::{env, fs, process};
+
+/// Where files are served from unless a directory is given on the command line.
+const DOCUMENT_ROOT: &str = "public";
+
+/// How long in-flight requests get to finish after Ctrl-C.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many connections are served at once; the pool can run or queue this many.
+const MAX_CONNECTIONS: usize = 80;
+
+/// How long to wait before accepting again after it failed, e.g. for lack of file descriptors.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
 
 fn main() {
+    let root = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DOCUMENT_ROOT.to_string());
+    let files = StaticFiles::new(&root).unwrap_or_else(|err| {
+        eprintln!("Cannot serve {}: {}", root, err);
+        process::exit(1);
+    });
+
     let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
+    let addr = listener.local_addr().unwrap();
 
-    let pool = ThreadPool::new(4); // 스레드 풀 생성
+    // Ctrl-C 를 누르면 새 연결을 받지 않도록 표시하고, 대기 중인 accept 를 깨우기 위해 스스로 접속
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = Arc::clone(&running);
+        ctrlc::set_handler(move || {
+            running.store(false, Ordering::SeqCst);
+            let _ = TcpStream::connect(addr);
+        })
+        .unwrap();
+    }
+
+    // 스레드 풀 생성, 부하가 걸리면 16개까지 늘리고 대기열이 가득 차면 새 연결을 거절
+    let pool = ThreadPool::builder()
+        .core_threads(4)
+        .max_threads(16)
+        .queue_capacity(64)
+        .rejection_policy(RejectionPolicy::Reject)
+        .build();
+
+    let router = Arc::new(router(files));
+    // 연결을 유지하여 같은 연결로 여러 요청을 처리하되, 5초 동안 요청이 없거나 100개를 처리하면 닫음
+    // 요청을 너무 느리게 보내거나 헤더나 본문이 너무 큰 클라이언트는 끊어서 워커를 붙잡지 못하게 함
+    let config = Arc::new(
+        ConnectionConfig::new()
+            .idle_timeout(Duration::from_secs(5))
+            .max_requests(100)
+            .header_timeout(Duration::from_secs(10))
+            .body_timeout(Duration::from_secs(30))
+            .write_timeout(Duration::from_secs(30))
+            .max_header_size(8 * 1024)
+            .max_body_size(1024 * 1024),
+    );
+    let limit = ConnectionLimit::new(MAX_CONNECTIONS);
 
     for stream in listener.incoming() {
-        let stream = stream.unwrap();
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                // 파일 디스크립터가 모자라는 등 일시적인 오류일 수 있으므로 잠시 뒤 다시 받음
+                println!("Failed to accept a connection: {}", err);
+                thread::sleep(ACCEPT_RETRY_DELAY);
+                continue;
+            }
+        };
+
+        // 동시 연결 수가 한도를 넘으면 503 으로 응답하고 닫음
+        let Some(permit) = limit.try_acquire() else {
+            reject_connection(stream, &config);
+            continue;
+        };
 
         // thread::spawn(|| {
-        //     handle_connection(stream);
+        //     serve_connection(stream, &router, &config);
         // }); // 매 요청마다 새 스레드를 생성하여 처리
 
-        pool.execute(|| {
-            handle_connection(stream);
-        }); // 스레드 풀을 이용하여 처리
+        // 스레드 풀을 이용하여 처리
+        // 풀이 작업을 거절하면 연결을 돌려받아 503 으로 응답할 수 있도록 슬롯을 통해 넘김
+        let stream = Arc::new(Mutex::new(Some(stream)));
+        let slot = Arc::clone(&stream);
+        let router = Arc::clone(&router);
+        let job_config = Arc::clone(&config);
+        let result = pool.execute(move || {
+            if let Some(stream) = take(&slot) {
+                serve_connection(stream, &router, &job_config);
+            }
+            drop(permit);
+        });
+        if let Err(err) = result {
+            println!("Rejecting connection: {}", err);
+            if let Some(stream) = take(&stream) {
+                reject_connection(stream, &config);
+            }
+        }
     }
 
     println!("Shutting down.");
-}
-
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 512];
-
-    stream.read(&mut buffer).unwrap();
 
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
-
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else if buffer.starts_with(sleep) {
-        thread::sleep(std::time::Duration::from_secs(5));
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND", "404.html")
-    };
+    // 처리 중인 요청이 끝나기를 기다리되, 제한 시간이 지나면 포기
+    let unfinished = pool.shutdown(SHUTDOWN_TIMEOUT);
+    if !unfinished.is_empty() {
+        println!(
+            "Gave up on {} running and {} queued requests.",
+            unfinished.running, unfinished.queued
+        );
+    }
+}
 
-    let mut file = File::open(filename).unwrap();
+fn take(slot: &Mutex<Option<TcpStream>>) -> Option<TcpStream> {
+    slot.lock().unwrap_or_else(PoisonError::into_inner).take()
+}
 
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
+fn router(files: StaticFiles) -> Router {
+    let sleep_files = files.clone();
+    let not_found_page = files.root().join("404.html");
 
-    let response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n{}",
-        status_line,
-        contents.len(),
-        contents
-    );
+    Router::new()
+        .get("/sleep", move |request, _| {
+            thread::sleep(Duration::from_secs(5));
+            sleep_files.serve(request, "index.html")
+        })
+        .get("/hello/:name", |_, params| {
+            Response::new(StatusCode::OK)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!(
+                    "Hello, {}!\n",
+                    params.get("name").unwrap_or_default()
+                ))
+        })
+        // 나머지 경로는 문서 루트의 파일로 응답
+        .get("/*path", move |request, params| {
+            let response = files.serve(request, params.get("path").unwrap_or_default());
+            if response.status() == StatusCode::NOT_FOUND {
+                return html_file(StatusCode::NOT_FOUND, &not_found_page);
+            }
+            response
+        })
+}
 
-    stream.write(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+fn html_file(status: StatusCode, path: &Path) -> Response {
+    match fs::read(path) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(err) => {
+            println!("Failed to read {}: {}", path.display(), err);
+            Response::new(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
@@ -0,0 +1,343 @@
+//! Dispatches requests to handlers by method and path pattern.
+
+use crate::http::{percent_decode, Method, Request, Response, StatusCode};
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+
+/// A table of routes, each a method, a path pattern and a handler.
+///
+/// A pattern is a path whose segments are matched literally, except for `:name`, which
+/// captures one segment, and a final `*name`, which captures the rest of the path. The first
+/// route added that matches wins. A path that no route matches gets a 404, one that only
+/// routes for other methods match gets a 405. `HEAD` requests go to the `GET` route unless
+/// a `HEAD` route was added.
+///
+/// ```
+/// use multi_thread_web_server::http::{Response, StatusCode};
+/// use multi_thread_web_server::router::Router;
+///
+/// let router = Router::new()
+///     .get("/users/:id", |_, params| {
+///         Response::new(StatusCode::OK).body(format!("user {}", params.get("id").unwrap()))
+///     })
+///     .get("/files/*path", |_, params| {
+///         Response::new(StatusCode::OK).body(params.get("path").unwrap().to_string())
+///     });
+/// ```
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Handler,
+}
+
+struct Route {
+    method: Method,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+/// The path segments captured by a route pattern, percent-decoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            not_found: Box::new(|_, _| {
+                Response::new(StatusCode::NOT_FOUND)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body("Not Found\n")
+            }),
+        }
+    }
+
+    /// Adds a route for `method` requests to paths matching `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` does not start with `/`, or has a wildcard before its last segment.
+    pub fn route<F>(mut self, method: Method, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        let pattern = parse_pattern(pattern);
+        self.routes.push(Route {
+            method,
+            pattern,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    /// Answers requests for paths no route matches, instead of a plain 404.
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    /// Runs the handler of the route matching `request`.
+    pub fn handle(&self, request: &Request) -> Response {
+        let Some(segments) = path_segments(&request.path) else {
+            return Response::new(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body("Malformed path\n");
+        };
+
+        let mut allowed = Vec::new();
+        let mut fallback = None;
+
+        for route in &self.routes {
+            let Some(params) = match_pattern(&route.pattern, &segments) else {
+                continue;
+            };
+
+            if route.method == request.method {
+                return (route.handler)(request, &params);
+            }
+            if route.method == Method::Get && request.method == Method::Head && fallback.is_none() {
+                fallback = Some((route, params));
+            }
+            allowed.push(route.method);
+        }
+
+        if let Some((route, params)) = fallback {
+            return (route.handler)(request, &params);
+        }
+        if allowed.is_empty() {
+            return (self.not_found)(request, &Params::default());
+        }
+
+        if allowed.contains(&Method::Get) {
+            allowed.push(Method::Head);
+        }
+        allowed.sort_by_key(|method| method.as_str());
+        allowed.dedup();
+        let allow: Vec<&str> = allowed.iter().map(Method::as_str).collect();
+
+        Response::new(StatusCode::METHOD_NOT_ALLOWED)
+            .header("Allow", allow.join(", "))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body("Method Not Allowed\n")
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let rest = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("route pattern {:?} does not start with '/'", pattern));
+    let parts: Vec<&str> = rest.split('/').collect();
+
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            if let Some(name) = part.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                assert!(
+                    i == parts.len() - 1,
+                    "wildcard in route pattern {:?} is not the last segment",
+                    pattern
+                );
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Splits a path into decoded segments, `None` if one of them does not decode.
+fn path_segments(path: &str) -> Option<Vec<String>> {
+    path.strip_prefix('/')
+        .unwrap_or(path)
+        .split('/')
+        .map(percent_decode)
+        .collect()
+}
+
+fn match_pattern(pattern: &[Segment], segments: &[String]) -> Option<Params> {
+    let mut params = Vec::new();
+
+    for (i, segment) in pattern.iter().enumerate() {
+        match segment {
+            Segment::Wildcard(name) => {
+                params.push((name.clone(), segments.get(i..)?.join("/")));
+                return Some(Params(params));
+            }
+            Segment::Literal(literal) => {
+                if segments.get(i) != Some(literal) {
+                    return None;
+                }
+            }
+            Segment::Param(name) => {
+                let value = segments.get(i).filter(|value| !value.is_empty())?;
+                params.push((name.clone(), value.clone()));
+            }
+        }
+    }
+
+    (pattern.len() == segments.len()).then_some(Params(params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Headers, Version};
+
+    fn request(method: Method, path: &str) -> Request {
+        Request {
+            method,
+            path: path.to_string(),
+            query: None,
+            version: Version::Http11,
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn echo(name: &'static str) -> impl Fn(&Request, &Params) -> Response + Send + Sync {
+        move |_, params| {
+            let params: Vec<String> = params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            Response::new(StatusCode::OK).body(format!("{} {}", name, params.join(" ")))
+        }
+    }
+
+    fn body(router: &Router, method: Method, path: &str) -> String {
+        let response = router.handle(&request(method, path));
+        String::from_utf8(response.body_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn matches_literals_params_and_wildcards() {
+        let router = Router::new()
+            .get("/", echo("index"))
+            .get("/users/new", echo("new"))
+            .get("/users/:id", echo("user"))
+            .get("/users/:id/posts/:post", echo("post"))
+            .get("/static/*path", echo("static"));
+
+        assert_eq!(body(&router, Method::Get, "/"), "index ");
+        assert_eq!(body(&router, Method::Get, "/users/new"), "new ");
+        assert_eq!(body(&router, Method::Get, "/users/42"), "user id=42");
+        assert_eq!(body(&router, Method::Get, "/users/a%20b"), "user id=a b");
+        assert_eq!(
+            body(&router, Method::Get, "/users/7/posts/9"),
+            "post id=7 post=9"
+        );
+        assert_eq!(
+            body(&router, Method::Get, "/static/css/site.css"),
+            "static path=css/site.css"
+        );
+        assert_eq!(body(&router, Method::Get, "/static/"), "static path=");
+    }
+
+    #[test]
+    fn answers_unknown_paths_with_404() {
+        let router = Router::new().get("/users/:id", echo("user"));
+
+        for path in ["/users", "/users/", "/users/1/extra", "/other"] {
+            let response = router.handle(&request(Method::Get, path));
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+
+        let router = router.not_found(|_, _| Response::new(StatusCode::NOT_FOUND).body("custom"));
+        assert_eq!(body(&router, Method::Get, "/other"), "custom");
+    }
+
+    #[test]
+    fn answers_other_methods_with_405() {
+        let router = Router::new()
+            .get("/todos", echo("list"))
+            .post("/todos", echo("create"))
+            .delete("/todos/:id", echo("delete"));
+
+        assert_eq!(body(&router, Method::Post, "/todos"), "create ");
+
+        let response = router.handle(&request(Method::Put, "/todos"));
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, POST"));
+    }
+
+    #[test]
+    fn serves_head_from_get_routes() {
+        let router = Router::new().get("/", echo("index"));
+        assert_eq!(body(&router, Method::Head, "/"), "index ");
+
+        let router = router.route(Method::Head, "/", echo("head"));
+        assert_eq!(body(&router, Method::Head, "/"), "head ");
+        assert_eq!(body(&router, Method::Get, "/"), "index ");
+    }
+
+    #[test]
+    fn rejects_undecodable_paths() {
+        let router = Router::new().get("/*path", echo("any"));
+        let response = router.handle(&request(Method::Get, "/%zz"));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    #[should_panic(expected = "not the last segment")]
+    fn rejects_wildcards_in_the_middle() {
+        let _ = Router::new().get("/*path/edit", echo("edit"));
+    }
+}
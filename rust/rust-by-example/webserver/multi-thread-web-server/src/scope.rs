@@ -0,0 +1,313 @@
+use std::{
+    marker::PhantomData,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use crate::{ExecuteError, ThreadPool};
+
+/// How long a worker waiting for its scope sleeps before looking for jobs to help with.
+const HELP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Spawns jobs that may borrow from the stack of the thread calling
+/// [`ThreadPool::scope`].
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    // invariant over both lifetimes, like `std::thread::Scope`
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeState {
+    pending: Mutex<usize>,
+    finished: Condvar,
+    panicked: AtomicBool,
+}
+
+impl ScopeState {
+    fn finish(&self) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        *pending -= 1;
+        if *pending == 0 {
+            self.finished.notify_all();
+        }
+    }
+}
+
+/// A job spawned in a scope, which counts as finished once it and everything it borrows
+/// have been dropped, whether it ran, panicked or was dropped from a full queue.
+struct ScopedJob {
+    job: Option<Box<dyn FnOnce() + Send>>,
+    state: Arc<ScopeState>,
+}
+
+impl Drop for ScopedJob {
+    fn drop(&mut self) {
+        drop(self.job.take());
+        self.state.finish();
+    }
+}
+
+impl ThreadPool {
+    /// Runs `f` with a [`Scope`] for spawning jobs that borrow local data.
+    ///
+    /// Every job spawned in the scope has finished before this returns, so unlike
+    /// [`execute`](ThreadPool::execute), the jobs need not be `'static`. Called from a job
+    /// running on this pool, the worker runs queued jobs while it waits.
+    ///
+    /// # Panics
+    ///
+    /// If `f` or any of the jobs panicked, once all of them have finished.
+    ///
+    /// ```
+    /// use multi_thread_web_server::ThreadPool;
+    ///
+    /// let pool = ThreadPool::new(4);
+    /// let mut rows = vec![vec![0u32; 8]; 4];
+    ///
+    /// pool.scope(|s| {
+    ///     for (y, row) in rows.iter_mut().enumerate() {
+    ///         s.spawn(move || row.iter_mut().for_each(|cell| *cell = y as u32))
+    ///             .unwrap();
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(rows[3], vec![3; 8]);
+    /// ```
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState {
+                pending: Mutex::new(0),
+                finished: Condvar::new(),
+                panicked: AtomicBool::new(false),
+            }),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+
+        // the jobs borrow what `f` borrows, so wait for them even if `f` panics
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.wait();
+
+        match result {
+            Err(payload) => panic::resume_unwind(payload),
+            Ok(_) if scope.state.panicked.load(Ordering::SeqCst) => {
+                panic!("a scoped job panicked")
+            }
+            Ok(value) => value,
+        }
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Queues `f` to run on the pool before the scope ends.
+    ///
+    /// Fails like [`ThreadPool::execute`] when the queue is full.
+    pub fn spawn<F>(&'scope self, f: F) -> Result<(), ExecuteError>
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(f);
+        // SAFETY: `ThreadPool::scope` does not return before every `ScopedJob` is dropped,
+        // and a `ScopedJob` drops the job first, so the job never outlives what it borrows.
+        let job: Box<dyn FnOnce() + Send + 'static> = unsafe { mem::transmute(job) };
+
+        *self
+            .state
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += 1;
+        let mut scoped = ScopedJob {
+            job: Some(job),
+            state: Arc::clone(&self.state),
+        };
+
+        self.pool.execute(move || {
+            let job = scoped.job.take().expect("a scoped job runs once");
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                scoped.state.panicked.store(true, Ordering::SeqCst);
+                drop(scoped);
+                // let the worker count the panic too
+                panic::resume_unwind(payload);
+            }
+        })
+    }
+
+    fn wait(&self) {
+        let shared = &self.pool.shared;
+
+        loop {
+            let pending = self
+                .state
+                .pending
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if *pending == 0 {
+                return;
+            }
+
+            if !shared.is_worker() {
+                drop(
+                    self.state
+                        .finished
+                        .wait_while(pending, |pending| *pending > 0)
+                        .unwrap_or_else(PoisonError::into_inner),
+                );
+                return;
+            }
+
+            // a worker blocking here could leave the jobs of this scope without a worker
+            drop(pending);
+            if !shared.help() {
+                let pending = self
+                    .state
+                    .pending
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                if *pending > 0 {
+                    drop(
+                        self.state
+                            .finished
+                            .wait_timeout(pending, HELP_INTERVAL)
+                            .unwrap_or_else(PoisonError::into_inner),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{panic, sync::atomic::AtomicUsize};
+
+    use super::*;
+    use crate::{tests::busy_pool, RejectionPolicy};
+
+    #[test]
+    fn waits_for_jobs_around_one_rejected_from_a_full_queue() {
+        let (pool, release) = busy_pool(RejectionPolicy::Reject);
+        let ran = AtomicUsize::new(0);
+
+        pool.scope(|s| {
+            s.spawn(|| {
+                ran.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+            let rejected = s.spawn(|| {
+                ran.fetch_add(10, Ordering::SeqCst);
+            });
+            assert_eq!(rejected, Err(ExecuteError::QueueFull));
+            drop(release);
+        });
+
+        assert_eq!(ran.into_inner(), 1);
+    }
+
+    #[test]
+    fn waits_for_the_job_that_dropped_the_oldest_one() {
+        let (pool, release) = busy_pool(RejectionPolicy::DropOldest);
+        let ran = AtomicUsize::new(0);
+
+        pool.scope(|s| {
+            s.spawn(|| {
+                ran.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+            s.spawn(|| {
+                ran.fetch_add(10, Ordering::SeqCst);
+            })
+            .unwrap();
+            drop(release);
+        });
+
+        assert_eq!(ran.into_inner(), 10);
+    }
+
+    #[test]
+    fn waits_for_queued_jobs_after_running_one_on_the_caller() {
+        let (pool, release) = busy_pool(RejectionPolicy::CallerRuns);
+        let ran = AtomicUsize::new(0);
+
+        pool.scope(|s| {
+            s.spawn(|| {
+                ran.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+            s.spawn(|| {
+                ran.fetch_add(10, Ordering::SeqCst);
+            })
+            .unwrap();
+            assert_eq!(ran.load(Ordering::SeqCst), 10);
+            drop(release);
+        });
+
+        assert_eq!(ran.into_inner(), 11);
+    }
+
+    #[test]
+    fn returns_when_jobs_are_spawned_after_shutdown() {
+        let pool = ThreadPool::new(1);
+        assert!(pool.shutdown(Duration::from_secs(1)).is_empty());
+        let ran = AtomicUsize::new(0);
+
+        pool.scope(|s| {
+            let spawned = s.spawn(|| {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(spawned, Err(ExecuteError::ShutDown));
+        });
+
+        assert_eq!(ran.into_inner(), 0);
+    }
+
+    #[test]
+    fn waits_for_every_job_before_passing_on_a_panic() {
+        let pool = ThreadPool::new(1);
+        let ran = AtomicUsize::new(0);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.spawn(|| panic!("scoped job failed")).unwrap();
+                s.spawn(|| {
+                    std::thread::sleep(Duration::from_millis(50));
+                    ran.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(ran.into_inner(), 1);
+    }
+
+    #[test]
+    fn a_worker_runs_the_jobs_of_its_own_scope() {
+        // a single worker, so the inner jobs only run if it helps while it waits
+        let pool = ThreadPool::new(1);
+        let mut sums = [0; 4];
+
+        pool.scope(|s| {
+            s.spawn(|| {
+                pool.scope(|inner| {
+                    for (i, sum) in sums.iter_mut().enumerate() {
+                        inner.spawn(move || *sum = i * 2).unwrap();
+                    }
+                });
+            })
+            .unwrap();
+        });
+
+        assert_eq!(sums, [0, 2, 4, 6]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    any::Any,
+    error::Error,
+    fmt,
+    sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError},
+    time::Duration,
+};
+
+/// Handle to a job submitted with [`ThreadPool::submit`](crate::ThreadPool::submit).
+///
+/// The result can be taken once: with [`join`](TaskHandle::join), or with
+/// [`try_join`](TaskHandle::try_join) and [`join_timeout`](TaskHandle::join_timeout), which
+/// return `None` while the job is still running.
+pub struct TaskHandle<T> {
+    receiver: Receiver<Result<T, JoinError>>,
+}
+
+impl<T> TaskHandle<T> {
+    pub(crate) fn new(receiver: Receiver<Result<T, JoinError>>) -> TaskHandle<T> {
+        TaskHandle { receiver }
+    }
+
+    /// Blocks until the job has finished and returns its result.
+    pub fn join(self) -> Result<T, JoinError> {
+        match self.receiver.recv() {
+            Ok(result) => result,
+            Err(_) => Err(JoinError::Cancelled),
+        }
+    }
+
+    /// Returns the result if the job has finished, without blocking.
+    pub fn try_join(&mut self) -> Option<Result<T, JoinError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(JoinError::Cancelled)),
+        }
+    }
+
+    /// Waits at most `timeout` for the job to finish.
+    pub fn join_timeout(&mut self, timeout: Duration) -> Option<Result<T, JoinError>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => Some(result),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Err(JoinError::Cancelled)),
+        }
+    }
+}
+
+/// Why a submitted job produced no result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    /// The job panicked with this message.
+    Panicked(String),
+    /// The job was dropped without running, or its result was already taken.
+    Cancelled,
+}
+
+impl JoinError {
+    pub(crate) fn panicked(payload: &(dyn Any + Send)) -> JoinError {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            String::from("job panicked")
+        };
+
+        JoinError::Panicked(message)
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked(message) => write!(f, "job panicked: {}", message),
+            JoinError::Cancelled => write!(f, "job was cancelled"),
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::{tests::busy_pool, RejectionPolicy, ThreadPool};
+
+    use super::*;
+
+    #[test]
+    fn join_returns_the_result() {
+        let pool = ThreadPool::new(2);
+
+        let handles: Vec<_> = (0..10)
+            .map(|i| pool.submit(move || i * i).unwrap())
+            .collect();
+        let squares: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_eq!(squares, (0..10).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn try_join_and_join_timeout_wait_for_the_job() {
+        let pool = ThreadPool::new(1);
+        let (release, released) = mpsc::channel::<()>();
+        let mut handle = pool.submit(move || released.recv().unwrap()).unwrap();
+
+        assert_eq!(handle.try_join(), None);
+        assert_eq!(handle.join_timeout(Duration::from_millis(20)), None);
+
+        release.send(()).unwrap();
+        assert_eq!(handle.join_timeout(Duration::from_secs(5)), Some(Ok(())));
+        // the result can only be taken once
+        assert_eq!(handle.join(), Err(JoinError::Cancelled));
+    }
+
+    #[test]
+    fn panics_are_returned_with_their_message() {
+        let pool = ThreadPool::new(1);
+
+        let handle = pool.submit(|| -> u32 { panic!("static message") }).unwrap();
+        assert_eq!(
+            handle.join(),
+            Err(JoinError::Panicked(String::from("static message")))
+        );
+
+        let handle = pool
+            .submit(|| -> u32 { panic!("formatted {}", 42) })
+            .unwrap();
+        assert_eq!(
+            handle.join(),
+            Err(JoinError::Panicked(String::from("formatted 42")))
+        );
+
+        // the worker survived both
+        assert_eq!(pool.submit(|| 7).unwrap().join(), Ok(7));
+    }
+
+    #[test]
+    fn jobs_dropped_from_the_queue_are_cancelled() {
+        let (pool, release) = busy_pool(RejectionPolicy::DropOldest);
+
+        let mut dropped = pool.submit(|| 1).unwrap();
+        let kept = pool.submit(|| 2).unwrap();
+        assert_eq!(dropped.try_join(), Some(Err(JoinError::Cancelled)));
+
+        drop(release);
+        assert_eq!(kept.join(), Ok(2));
+        assert_eq!(dropped.join(), Err(JoinError::Cancelled));
+    }
+}